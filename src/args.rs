@@ -1,139 +1,458 @@
-use std::env;
-use std::fmt::{Display, Formatter};
-use std::process::exit;
+use clap::{Parser, Subcommand};
 
-#[derive(Debug)]
-pub enum ArgParseError {
-    MissingArgumentFor(&'static str),
-    UnknownArgument(String),
-    IllegalArgument(&'static str),
-}
+use crate::coords::BoundingBox;
+use crate::cost::Metric;
+use crate::generate::Generator;
+use crate::grid::SnapFilter;
+use crate::queue::QueueKind;
+use crate::router::{Algorithm, Location};
+use crate::svg::Projection;
 
+pub type ArgParseError = clap::Error;
 
-pub mod flag {
-    pub type Type = u32;
+const EXAMPLES: &str = "\
+Examples:
+  router query -g germany.fmi -q germany.que --threads 8
+  router route -g germany.bin --from 48.746,9.098 --to 638394 --gpx route.gpx
+  router tour -g germany.bin --start 638394 --stop 8371825 --stop 48.746,9.098 --round-trip
+  router nearest -g germany.fmi --lat 48.746 --lon 9.098 --naive
+  router one-to-all -g germany.fmi -s 638394 -t 8371825
+  router bench -g germany.bin -n 100 --ranks --json bench.json
+  router convert germany.fmi germany.bin
+  router export -g germany.bin --bbox 48.7,9.1,48.8,9.2 -o stuttgart.geojson";
 
-    pub const SHOW_NAIVE_NODE: Type = 1;
+#[derive(Parser, Debug)]
+#[command(
+    name = "router",
+    version,
+    about = "Router. The ultimate route finder. Written by Thorben Bernhardt & Tobias Schaberl.",
+    after_help = EXAMPLES
+)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Command,
 }
 
-const FLAGS: [(&str, flag::Type); 1] = [("--naive", flag::SHOW_NAIVE_NODE)]; 
-
-impl Display for ArgParseError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let help = "\nUse --help for more infos and examples.";
-        match self {
-            ArgParseError::MissingArgumentFor(arg) => {
-                write!(f, "Error parsing arguments | Missing argument for {}{}", arg, help)
-            }
-            ArgParseError::UnknownArgument(arg) => {
-                write!(f, "Error parsing arguments | Unknown argument '{}'{}", arg, help)
-            }
-            ArgParseError::IllegalArgument(err) => {
-                write!(f, "Error parsing arguments | {}{}", err, help)
-            }
-        }
-    }
-}
-
-pub struct Args {
-    pub cmd: String,
-    pub lat: Option<f64>,
-    pub lon: Option<f64>,
-    pub graph_file: Option<String>,
-    pub query_file: Option<String>,
-    pub source_node: Option<i64>,
-    pub target_node: Option<i64>,
-    pub flags: flag::Type,
-    pub thread_count: Option<u32>,
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Convert a graph between the FMI text format and the binary format (chosen by the '.bin' extension)
+    Convert {
+        /// The graph file to read
+        input: String,
+        /// The graph file to write
+        output: String,
+    },
+    /// Answer all queries of a query file multi-threaded
+    Query {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The query file, one '<waypoint> <waypoint> ... [profile]' query per line, each waypoint a '<node id>' or '<lat>,<lon>'
+        #[arg(short, long)]
+        query: String,
+        /// The amount of threads to be used. Default maximal 4
+        #[arg(long)]
+        threads: Option<u32>,
+        /// Write the routes as GeoJSON LineStrings to this file
+        #[arg(long)]
+        geojson: Option<String>,
+        /// Write the routes as GPX tracks to this file
+        #[arg(long)]
+        gpx: Option<String>,
+        /// Print the search space of every query and in total
+        #[arg(long)]
+        stats: bool,
+        /// Which nodes coordinates may be snapped to: 'all', 'outgoing' (with outgoing edges) or 'largest-scc'
+        #[arg(long, default_value = "all")]
+        snap: SnapFilter,
+        /// What is minimised: 'cost' (as stored), 'distance', 'time' or 'weighted:length=<w>,time=<w>,climb=<w>'
+        #[arg(long, default_value = "cost")]
+        metric: Metric,
+        /// A JSON file with routing profiles in addition to 'car', 'bicycle' and 'foot'
+        #[arg(long)]
+        profiles: Option<String>,
+        /// A file of cost updates applied to the graph, e.g. 'block 3,5' or 'multiply 17 1.5' per line
+        #[arg(long)]
+        updates: Option<String>,
+        /// Don't enter the polygons of this GeoJSON file, can be given several times
+        #[arg(long)]
+        avoid: Vec<String>,
+        /// Don't enter the box '<min lat>,<min lon>,<max lat>,<max lon>', can be given several times
+        #[arg(long, allow_hyphen_values = true)]
+        avoid_bbox: Vec<BoundingBox>,
+    },
+    /// Compute a single route between two node ids or coordinates
+    Route {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The start as '<node id>' or '<lat>,<lon>'
+        #[arg(long, allow_hyphen_values = true, required_unless_present = "points")]
+        from: Option<Location>,
+        /// The end as '<node id>' or '<lat>,<lon>'
+        #[arg(long, allow_hyphen_values = true, required_unless_present = "points")]
+        to: Option<Location>,
+        /// The start, via and end coordinates as encoded polyline instead of '--from', '--via' and '--to'
+        #[arg(long, allow_hyphen_values = true, conflicts_with_all = ["from", "to", "via"])]
+        points: Option<String>,
+        /// Print the route geometry as encoded polyline
+        #[arg(long)]
+        polyline: bool,
+        /// The decimal places of encoded polylines
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(5..=6))]
+        precision: u32,
+        /// Write the route and the snapped start and end as GeoJSON to this file
+        #[arg(long)]
+        geojson: Option<String>,
+        /// Write the route as GPX track with the snapped start and end as waypoints to this file
+        #[arg(long)]
+        gpx: Option<String>,
+        /// Print the search space of the query
+        #[arg(long)]
+        stats: bool,
+        /// Which nodes coordinates may be snapped to: 'all', 'outgoing' (with outgoing edges) or 'largest-scc'
+        #[arg(long, default_value = "all")]
+        snap: SnapFilter,
+        /// Snap coordinates to this many nearest nodes and try the next ones if there is no route
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        candidates: u32,
+        /// What is minimised: 'cost' (as stored), 'distance', 'time' or 'weighted:length=<w>,time=<w>,climb=<w>'
+        #[arg(long, default_value = "cost")]
+        metric: Metric,
+        /// A file of cost updates applied to the graph, e.g. 'block 3,5' or 'multiply 17 1.5' per line
+        #[arg(long)]
+        updates: Option<String>,
+        /// Don't enter the polygons of this GeoJSON file, can be given several times
+        #[arg(long)]
+        avoid: Vec<String>,
+        /// Don't enter the box '<min lat>,<min lon>,<max lat>,<max lon>', can be given several times
+        #[arg(long, allow_hyphen_values = true)]
+        avoid_bbox: Vec<BoundingBox>,
+        /// A '<node id>' or '<lat>,<lon>' to pass on the way, can be given several times in order
+        #[arg(long, allow_hyphen_values = true)]
+        via: Vec<Location>,
+    },
+    /// Find a good order to visit several stops and the route over them
+    Tour {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The start as '<node id>' or '<lat>,<lon>'
+        #[arg(long, allow_hyphen_values = true)]
+        start: Location,
+        /// A '<node id>' or '<lat>,<lon>' to visit, can be given several times
+        #[arg(long = "stop", allow_hyphen_values = true, required = true)]
+        stops: Vec<Location>,
+        /// Where the tour ends after all stops, by default at the last stop visited
+        #[arg(long, allow_hyphen_values = true, conflicts_with = "round_trip")]
+        end: Option<Location>,
+        /// Return to the start after the last stop
+        #[arg(long)]
+        round_trip: bool,
+        /// Use nearest neighbour with 2-opt and Or-opt even for few stops, which are solved exactly otherwise
+        #[arg(long)]
+        heuristic: bool,
+        /// What is minimised: 'cost' (as stored), 'distance', 'time' or 'weighted:length=<w>,time=<w>,climb=<w>'
+        #[arg(long, default_value = "cost")]
+        metric: Metric,
+        /// Write the tour and the stops as GeoJSON to this file
+        #[arg(long)]
+        geojson: Option<String>,
+    },
+    /// Find the node nearest to a coordinate
+    Nearest {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The latitude, e.g. '48.7758'
+        #[arg(long, allow_negative_numbers = true)]
+        lat: f64,
+        /// The longitude, e.g. '9.1829'
+        #[arg(long, allow_negative_numbers = true)]
+        lon: f64,
+        /// Search the node also naively
+        #[arg(long)]
+        naive: bool,
+        /// Which nodes may be found: 'all', 'outgoing' (with outgoing edges) or 'largest-scc'
+        #[arg(long, default_value = "all")]
+        snap: SnapFilter,
+        /// Write the location and the nearest node as GeoJSON Points to this file
+        #[arg(long)]
+        geojson: Option<String>,
+    },
+    /// Run a one-to-all dijkstra and look up the distance to a target
+    OneToAll {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The source node, e.g. '638394'
+        #[arg(short, long, required_unless_present = "target")]
+        source: Option<usize>,
+        /// The target node, e.g. '8371825'. Asked for on the console if missing. Without a source the
+        /// distances from all nodes to the target are computed instead.
+        #[arg(short, long)]
+        target: Option<usize>,
+        /// What is minimised: 'cost' (as stored), 'distance', 'time' or 'weighted:length=<w>,time=<w>,climb=<w>'
+        #[arg(long, default_value = "cost")]
+        metric: Metric,
+        /// A file of cost updates applied to the graph, e.g. 'block 3,5' or 'multiply 17 1.5' per line
+        #[arg(long)]
+        updates: Option<String>,
+        /// Write the route to the target as a GeoJSON LineString to this file
+        #[arg(long, requires = "target")]
+        geojson: Option<String>,
+        /// Use parallel delta-stepping with this many threads instead of dijkstra
+        #[arg(long)]
+        threads: Option<u32>,
+        /// The bucket width of delta-stepping, defaults to the mean edge cost
+        #[arg(long, requires = "threads")]
+        delta: Option<i64>,
+        /// Save the distances to this file, to look them up later with 'lookup'
+        #[arg(long)]
+        save: Option<String>,
+        /// Also save the predecessor of every node on its shortest path
+        #[arg(long, requires = "save", conflicts_with = "threads")]
+        pred: bool,
+        /// Write 'node_id,lat,lon,distance' of all nodes to this CSV file
+        #[arg(long)]
+        csv: Option<String>,
+        /// Write the shortest-path tree as GeoJSON to this file, one LineString per tree edge
+        #[arg(long, conflicts_with = "threads")]
+        tree: Option<String>,
+    },
+    /// Look up distances in a file saved by 'one-to-all --save'
+    Lookup {
+        /// The distance file
+        #[arg(short, long)]
+        distances: String,
+        /// The nodes to look up, comma separated. Read from the console line by line if missing
+        #[arg(short, long, value_delimiter = ',')]
+        targets: Vec<usize>,
+        /// Also print the nodes of the shortest path, needs a file saved with '--pred'
+        #[arg(long)]
+        path: bool,
+    },
+    /// Answer '<waypoint> <waypoint> ... [profile]' queries over TCP, one per line
+    ///
+    /// Every waypoint is a '<node id>' or '<lat>,<lon>', routes over via points are answered as
+    /// '<cost> = <leg> + <leg>'. Cost updates like 'block 3,5' or 'reset' change the costs for the rest
+    /// of the connection and are answered with 'ok', invalid lines with 'error: <reason>'.
+    Serve {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The address to listen on
+        #[arg(long, default_value = "127.0.0.1:7878")]
+        addr: String,
+        /// Which nodes coordinates may be snapped to: 'all', 'outgoing' (with outgoing edges) or 'largest-scc'
+        #[arg(long, default_value = "all")]
+        snap: SnapFilter,
+        /// What queries without a profile minimise: 'cost' (as stored), 'distance', 'time' or 'weighted:length=<w>,time=<w>,climb=<w>'
+        #[arg(long, default_value = "cost")]
+        metric: Metric,
+        /// A JSON file with routing profiles in addition to 'car', 'bicycle' and 'foot'
+        #[arg(long)]
+        profiles: Option<String>,
+        /// A file of cost updates applied to the graph, e.g. 'block 3,5' or 'multiply 17 1.5' per line
+        #[arg(long)]
+        updates: Option<String>,
+        /// Don't enter the polygons of this GeoJSON file, can be given several times
+        #[arg(long)]
+        avoid: Vec<String>,
+        /// Don't enter the box '<min lat>,<min lon>,<max lat>,<max lon>', can be given several times
+        #[arg(long, allow_hyphen_values = true)]
+        avoid_bbox: Vec<BoundingBox>,
+    },
+    /// Measure the query times of random queries or of a query file
+    Bench {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// Use the queries of this file instead of random ones
+        #[arg(short, long, conflicts_with = "ranks")]
+        query: Option<String>,
+        /// The amount of random queries, or of random sources if '--ranks' is given
+        #[arg(short = 'n', long, default_value_t = 1000)]
+        count: usize,
+        /// The seed of the random queries
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+        /// Stratify the queries by dijkstra rank, i.e. query the 2^r-th settled node of every source
+        #[arg(long)]
+        ranks: bool,
+        /// The algorithm to be measured
+        #[arg(long, default_value = "dijkstra")]
+        algorithm: Algorithm,
+        /// What is minimised: 'cost' (as stored), 'distance', 'time' or 'weighted:length=<w>,time=<w>,climb=<w>'
+        #[arg(long, default_value = "cost")]
+        metric: Metric,
+        /// The amount of threads running queries at the same time
+        #[arg(long, default_value_t = 1)]
+        threads: u32,
+        /// The priority queues of dijkstra to compare, comma separated: 'binary', 'radix' or 'dary'
+        #[arg(long, value_delimiter = ',', default_value = "binary")]
+        queue: Vec<QueueKind>,
+        /// Run dijkstra on the compact graph with 32 bit ids and costs, only with '--metric cost'
+        #[arg(long)]
+        compact: bool,
+        /// Write the results as JSON to this file
+        #[arg(long)]
+        json: Option<String>,
+    },
+    /// Write the edges inside a bounding box as GeoJSON
+    Export {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The area as '<min lat>,<min lon>,<max lat>,<max lon>'
+        #[arg(long, allow_hyphen_values = true)]
+        bbox: BoundingBox,
+        /// The GeoJSON file to write
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Render the graph and optionally a route as SVG image
+    Render {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The area as '<min lat>,<min lon>,<max lat>,<max lon>'. Default is the whole graph
+        #[arg(long, allow_hyphen_values = true)]
+        bbox: Option<BoundingBox>,
+        /// 'equirectangular' or 'web-mercator'
+        #[arg(long, default_value = "equirectangular")]
+        projection: Projection,
+        /// The width of the image in pixels
+        #[arg(long, default_value_t = 1024)]
+        width: u32,
+        /// The start of a route to draw as '<node id>' or '<lat>,<lon>'
+        #[arg(long, allow_hyphen_values = true, requires = "to")]
+        from: Option<Location>,
+        /// The end of a route to draw as '<node id>' or '<lat>,<lon>'
+        #[arg(long, allow_hyphen_values = true, requires = "from")]
+        to: Option<Location>,
+        /// Also draw the nodes settled by dijkstra while searching the route
+        #[arg(long, requires = "from")]
+        settled: bool,
+        /// Also draw the quadrants of the nearest data structure
+        #[arg(long)]
+        quadtree: bool,
+        /// The SVG file to write
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Write a synthetic graph, e.g. for benchmarks without a map
+    Generate {
+        /// The kind of graph, 'grid' or 'geometric'
+        kind: Generator,
+        /// The approximate amount of nodes
+        #[arg(short = 'n', long, default_value_t = 10000)]
+        nodes: usize,
+        /// The maximum edge cost of grid graphs, geometric graphs use the distance in meters
+        #[arg(long, default_value_t = 100)]
+        max_cost: i64,
+        #[arg(long, default_value_t = 42)]
+        seed: u64,
+        /// The graph file to write, '.bin' files are written in the binary format
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Cut out the nodes inside a bounding box or polygon, keeping the edges with both ends inside
+    Extract {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The area as '<min lat>,<min lon>,<max lat>,<max lon>'
+        #[arg(long, required_unless_present = "polygon", conflicts_with = "polygon", allow_hyphen_values = true)]
+        bbox: Option<BoundingBox>,
+        /// A GeoJSON file with the polygons of the area
+        #[arg(long)]
+        polygon: Option<String>,
+        /// The graph file to write, '.bin' files are written in the binary format
+        #[arg(short, long)]
+        output: String,
+        /// Write the '<new id> <original id>' mapping of the nodes to this file
+        #[arg(long)]
+        mapping: Option<String>,
+    },
+    /// Compute the strongly connected components and optionally extract the largest one
+    Components {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// Write the largest component with renumbered nodes to this graph file
+        #[arg(long)]
+        largest: Option<String>,
+        /// Write the '<new id> <original id>' mapping of the largest component to this file
+        #[arg(long, requires = "largest")]
+        mapping: Option<String>,
+    },
+    /// Check the graph for broken offsets, edges, costs and coordinates
+    Validate {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// The maximum amount of problems to print
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Print statistics about a graph
+    Stats {
+        /// The graph file
+        #[arg(short, long)]
+        graph: String,
+        /// Also build the nearest data structure to report its memory usage
+        #[arg(long)]
+        tree: bool,
+        /// Write the statistics as JSON to this file
+        #[arg(long)]
+        json: Option<String>,
+    },
 }
 
 impl Args {
-    fn empty() -> Self {
-        Self {
-            cmd: "".to_string(),
-            lat: None,
-            lon: None,
-            graph_file: None,
-            query_file: None,
-            source_node: None,
-            target_node: None,
-            flags: 0,
-            thread_count: None,
-        }
+    /// Parses the process arguments. Printing help or errors and exiting is left to the caller.
+    pub fn parse_env() -> Result<Self, ArgParseError> {
+        Self::try_parse()
     }
+}
 
-    pub fn parse() -> Result<Self, ArgParseError> {
-        let mut iter = env::args();
-        let mut result = Self::empty();
-        //First argument should be never empty, so unwrap is ok
-        result.cmd = iter.next().unwrap();
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
 
-        while let Some(arg) = iter.next() {
-            match arg.as_str() {
-                "--help" => {
-                    println!(include_str!("help.txt"));
+    use super::{Args, Command};
 
-                    exit(0);
-                }
-                "-lat" => {
-                    result.lat = Some(
-                        iter.next()
-                            .ok_or(ArgParseError::MissingArgumentFor("-lat"))?
-                            .parse::<f64>().map_err(|_| ArgParseError::IllegalArgument("-lat: Wrong format. Expected Something like '48.7758'."))?
-                    );
-                }
-                "-lon" => {
-                    result.lon = Some(
-                        iter.next()
-                            .ok_or(ArgParseError::MissingArgumentFor("-lon"))?
-                            .parse::<f64>().map_err(|_| ArgParseError::IllegalArgument("-lon: Wrong format. Expected Something like '9.1829'."))?
-                    );
-                }
-                "-graph" => {
-                    result.graph_file = Some(
-                        iter.next().ok_or(ArgParseError::MissingArgumentFor("-graph"))?.clone()
-                    );
-                }
-                "-que" => {
-                    result.query_file = Some(
-                        iter.next().ok_or(ArgParseError::MissingArgumentFor("-que"))?.clone()
-                    );
-                }
-                "-s" => {
-                    result.source_node = Some(
-                        iter.next()
-                            .ok_or(ArgParseError::MissingArgumentFor("-s"))?
-                            .parse::<i64>().map_err(|_| ArgParseError::IllegalArgument("-s: Wrong format. Expected something like '638394'."))?
-                    );
-                },
-                "-t" => {
-                    result.target_node = Some(
-                        iter.next()
-                            .ok_or(ArgParseError::MissingArgumentFor("-t"))?
-                            .parse::<i64>().map_err(|_| ArgParseError::IllegalArgument("-s: Wrong format. Expected something like '8371825'."))?
-                    );
-                },
-                "--threads" => {
-                    result.thread_count = Some(
-                        iter.next()
-                            .ok_or(ArgParseError::MissingArgumentFor("--threads"))?
-                            .parse::<u32>().map_err(|_| ArgParseError::IllegalArgument("--threads: Wrong format. Expected something line '12'"))?
-                   );
-                }
-                _ => {
-                    if !FLAGS
-                    .iter()
-                    .filter(|(str, _)| arg == *str)
-                    .any(|(_, flag)| {
-                        result.flags |= *flag;
-                        true
-                    }) {
-                        return Err(ArgParseError::UnknownArgument(arg.clone()));            
-                    }
+    #[test]
+    fn verify() {
+        Args::command().debug_assert();
+    }
 
-                }
+    #[test]
+    fn one_to_all() {
+        let args = Args::try_parse_from(["router", "one-to-all", "-g", "a.fmi", "-s", "5"]).unwrap();
+        match args.command {
+            Command::OneToAll { graph, source, target, .. } => {
+                assert_eq!(graph, "a.fmi");
+                assert_eq!(source, Some(5));
+                assert_eq!(target, None);
             }
+            _ => panic!("Wrong subcommand"),
         }
-        Ok(result)
+        assert!(Args::try_parse_from(["router", "one-to-all", "-g", "a.fmi", "-t", "7"]).is_ok());
+        assert!(Args::try_parse_from(["router", "one-to-all", "-g", "a.fmi"]).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn route_points() {
+        assert!(Args::try_parse_from(["router", "route", "-g", "a.fmi", "--points", "_p~iF~ps|U_ulLnnqC"]).is_ok());
+        assert!(Args::try_parse_from(["router", "route", "-g", "a.fmi", "--from", "1", "--points", "_p~iF~ps|U"]).is_err());
+        assert!(Args::try_parse_from(["router", "route", "-g", "a.fmi", "--from", "1"]).is_err());
+    }
+
+    #[test]
+    fn negative_coordinates() {
+        let args = Args::try_parse_from(["router", "nearest", "-g", "a.fmi", "--lat", "-33.9", "--lon", "18.4"]).unwrap();
+        assert!(matches!(args.command, Command::Nearest { lat, .. } if lat == -33.9));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::coords::{BoundingBox, Coords, Polygon};
+use crate::cost::CostFunction;
+use crate::edge::EdgeCost;
+use crate::grid::NodeTree;
+use crate::Graph;
+
+/// A zone routes must not enter, e.g. a construction area
+#[derive(Clone, PartialEq, Debug)]
+pub enum Area {
+    Bbox(BoundingBox),
+    Polygon(Polygon),
+}
+
+impl Area {
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        match self {
+            Area::Bbox(bbox) => Some(*bbox),
+            Area::Polygon(polygon) => polygon.bbox(),
+        }
+    }
+
+    pub fn contains(&self, coords: &Coords) -> bool {
+        match self {
+            Area::Bbox(bbox) => bbox.contains(coords),
+            Area::Polygon(polygon) => polygon.contains(coords),
+        }
+    }
+}
+
+/// Marks the nodes inside any of the areas. The tree must index all nodes, it narrows every area
+/// down to the nodes in its bounding box.
+pub fn blocked_nodes(graph: &Graph, tree: &NodeTree, areas: &[Area]) -> Vec<bool> {
+    let mut blocked = vec![false; graph.node_count()];
+    for area in areas {
+        let Some(bbox) = area.bbox() else { continue };
+        for node in tree.nodes_in_bbox(graph.nodes(), &bbox) {
+            if area.contains(&graph.nodes()[node].coords) {
+                blocked[node] = true;
+            }
+        }
+    }
+    blocked
+}
+
+/// Wraps a cost function and forbids all edges from or to blocked nodes
+pub struct Avoid<'a> {
+    costs: &'a dyn CostFunction,
+    blocked: &'a [bool],
+}
+
+impl<'a> Avoid<'a> {
+    pub fn new(costs: &'a dyn CostFunction, blocked: &'a [bool]) -> Self {
+        Self { costs, blocked }
+    }
+
+    pub fn is_blocked(&self, node: usize) -> bool {
+        self.blocked.get(node).copied().unwrap_or(false)
+    }
+
+    pub fn blocked_count(&self) -> usize {
+        self.blocked.iter().filter(|blocked| **blocked).count()
+    }
+}
+
+impl CostFunction for Avoid<'_> {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        self.costs.cost(graph, edge)
+    }
+
+    fn allows(&self, graph: &Graph, edge: usize) -> bool {
+        let (src, trg) = (graph.edges()[edge].src as usize, graph.edges()[edge].trg as usize);
+        !self.is_blocked(src) && !self.is_blocked(trg) && self.costs.allows(graph, edge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{blocked_nodes, Area, Avoid};
+    use crate::coords::{BoundingBox, Coords, Polygon};
+    use crate::cost::StoredCost;
+    use crate::edge::Edge;
+    use crate::grid::NodeTree;
+    use crate::router::{self, SearchTrace};
+    use crate::Graph;
+
+    #[test]
+    fn detour() {
+        // The direct way from 0 to 2 leads over 1, the detour over 3
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(48.0, 9.1), Coords::deg(48.0, 9.2), Coords::deg(48.1, 9.1)];
+        let edges = vec![Edge::new(0, 1, 1), Edge::new(1, 2, 1), Edge::new(0, 3, 5), Edge::new(3, 2, 5)];
+        let graph = Graph::from_edges(coords, edges);
+        let tree = NodeTree::build(graph.nodes());
+
+        let square = Polygon::new(vec![Coords::deg(47.95, 9.05), Coords::deg(47.95, 9.15), Coords::deg(48.05, 9.15), Coords::deg(48.05, 9.05)], Vec::new());
+        let blocked = blocked_nodes(&graph, &tree, &[Area::Polygon(square)]);
+        assert_eq!(blocked, vec![false, true, false, false]);
+
+        let avoid = Avoid::new(&StoredCost, &blocked);
+        let mut trace = SearchTrace::default().with_pred();
+        assert_eq!(router::shortest_path_with(&graph, &avoid, 0, 2, &mut trace), 10);
+        assert_eq!(trace.path_to(&graph, 0, 2).nodes(), vec![0, 3, 2]);
+
+        let everything = Area::Bbox(BoundingBox::new(Coords::deg(47.0, 9.05), Coords::deg(49.0, 9.15)));
+        let blocked = blocked_nodes(&graph, &tree, &[everything]);
+        let avoid = Avoid::new(&StoredCost, &blocked);
+        assert_eq!(avoid.blocked_count(), 2);
+        assert_eq!(router::shortest_path_with(&graph, &avoid, 0, 2, &mut trace), -1);
+    }
+}
@@ -0,0 +1,256 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::compact::CompactGraph;
+use crate::cost::Metric;
+use crate::edge::EdgeCost;
+use crate::queue::QueueKind;
+use crate::rng::Rng;
+use crate::router::{self, Algorithm, SearchTrace};
+use crate::Graph;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BenchQuery {
+    pub start: usize,
+    pub goal: usize,
+    /// The dijkstra rank of the goal as power of two, i.e. the goal was the `2^rank`-th settled node
+    pub rank: Option<u32>,
+}
+
+/// `count` uniformly random source/target pairs, none if there are no nodes
+pub fn random_queries(node_count: usize, count: usize, seed: u64) -> Vec<BenchQuery> {
+    let mut rng = Rng::new(seed);
+    let node_count = node_count as u64;
+    let count = if node_count == 0 { 0 } else { count };
+    (0..count)
+        .map(|_| BenchQuery {
+            start: rng.below(node_count) as usize,
+            goal: rng.below(node_count) as usize,
+            rank: None,
+        })
+        .collect()
+}
+
+/// For `sources` random sources, one query to the `2^r`-th settled node for every rank `r` which is reached.
+/// Unlike uniform queries this covers short, medium and long distances evenly. None if there are no nodes.
+pub fn rank_queries(graph: &BenchGraph, metric: Metric, sources: usize, seed: u64) -> Vec<BenchQuery> {
+    let mut rng = Rng::new(seed);
+    let mut queries = Vec::new();
+    let sources = if graph.node_count() == 0 { 0 } else { sources };
+    for _ in 0..sources {
+        let start = rng.below(graph.node_count() as u64) as usize;
+        let settled = graph.settled_order(metric, start);
+
+        let mut rank = 0;
+        while (1usize << rank) < settled.len() {
+            queries.push(BenchQuery { start, goal: settled[1 << rank], rank: Some(rank) });
+            rank += 1;
+        }
+    }
+    queries
+}
+
+/// The representation the queries run on
+#[derive(Clone)]
+pub enum BenchGraph {
+    Full(Arc<Graph>),
+    /// Only supports dijkstra on the stored costs
+    Compact(Arc<CompactGraph>),
+}
+
+impl BenchGraph {
+    pub fn node_count(&self) -> usize {
+        match self {
+            BenchGraph::Full(graph) => graph.node_count(),
+            BenchGraph::Compact(graph) => graph.node_count(),
+        }
+    }
+
+    /// Compact graphs never have negative costs
+    pub fn has_negative_costs(&self) -> bool {
+        match self {
+            BenchGraph::Full(graph) => graph.edges().iter().any(|edge| edge.cost < 0),
+            BenchGraph::Compact(_) => false,
+        }
+    }
+
+    /// All nodes reachable from `start` in the order dijkstra settles them
+    fn settled_order(&self, metric: Metric, start: usize) -> Vec<usize> {
+        match self {
+            BenchGraph::Full(graph) => {
+                let mut trace = SearchTrace::default().with_settled();
+                router::shortest_paths_with(graph, &metric, start, &mut trace);
+                trace.settled.unwrap()
+            }
+            BenchGraph::Compact(graph) => graph.settled_order(start),
+        }
+    }
+
+    fn shortest_path(&self, algorithm: Algorithm, metric: Metric, queue: QueueKind, start: usize, goal: usize) -> EdgeCost {
+        match self {
+            BenchGraph::Full(graph) => algorithm.shortest_path(graph, &metric, start, goal, queue),
+            BenchGraph::Compact(graph) => graph.shortest_path(start, goal, queue),
+        }
+    }
+}
+
+/// Timing summary of a set of queries, times are in microseconds
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    pub fn of(times: &mut [Duration]) -> Summary {
+        times.sort();
+        let micros = |index: usize| times.get(index).map_or(0.0, |time| time.as_secs_f64() * 1e6);
+        let count = times.len();
+        let total = times.iter().map(|time| time.as_secs_f64() * 1e6).sum::<f64>();
+        Summary {
+            count,
+            mean: total / count.max(1) as f64,
+            median: micros(count / 2),
+            // The nearest-rank percentile
+            p99: micros(((count as f64 * 0.99).ceil() as usize).max(1) - 1),
+            max: micros(count.saturating_sub(1)),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "count": self.count,
+            "mean_us": self.mean,
+            "median_us": self.median,
+            "p99_us": self.p99,
+            "max_us": self.max,
+        })
+    }
+}
+
+pub struct BenchReport {
+    pub algorithm: Algorithm,
+    pub metric: Metric,
+    pub queue: QueueKind,
+    pub compact: bool,
+    pub threads: u32,
+    pub seed: u64,
+    pub wall_time: Duration,
+    pub total: Summary,
+    /// The summaries of rank stratified queries, by rank
+    pub ranks: Vec<(u32, Summary)>,
+}
+
+impl BenchReport {
+    /// Queries per second over all threads
+    pub fn throughput(&self) -> f64 {
+        self.total.count as f64 / self.wall_time.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "algorithm": self.algorithm.to_string(),
+            "metric": self.metric.to_string(),
+            "queue": self.queue.to_string(),
+            "compact": self.compact,
+            "threads": self.threads,
+            "seed": self.seed,
+            "wall_time_ms": self.wall_time.as_secs_f64() * 1e3,
+            "throughput_qps": self.throughput(),
+            "total": self.total.to_json(),
+            "ranks": self.ranks.iter().map(|(rank, summary)| {
+                let mut value = summary.to_json();
+                value["rank"] = json!(rank);
+                value
+            }).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Runs all queries on `thread_count` threads and measures every single query
+pub fn run(graph: BenchGraph, queries: Arc<Vec<BenchQuery>>, algorithm: Algorithm, metric: Metric, queue: QueueKind, thread_count: u32, seed: u64) -> BenchReport {
+    let next = Arc::new(AtomicUsize::new(0));
+    let times = Arc::new(Mutex::new(vec![Duration::ZERO; queries.len()]));
+
+    let now = Instant::now();
+    let compact = matches!(graph, BenchGraph::Compact(_));
+    let handles = (0..thread_count.max(1))
+        .map(|_| {
+            let (graph, queries, next, times) = (graph.clone(), queries.clone(), next.clone(), times.clone());
+            thread::spawn(move || {
+                let mut local = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(query) = queries.get(index) else { break };
+                    let now = Instant::now();
+                    graph.shortest_path(algorithm, metric, queue, query.start, query.goal);
+                    local.push((index, now.elapsed()));
+                }
+
+                let mut times = times.lock().unwrap();
+                local.into_iter().for_each(|(index, time)| times[index] = time);
+            })
+        })
+        .collect::<Vec<_>>();
+    handles.into_iter().for_each(|handle| handle.join().unwrap());
+    let wall_time = now.elapsed();
+
+    let mut times = std::mem::take(&mut *times.lock().unwrap());
+    let mut ranks = Vec::new();
+    if let Some(max_rank) = queries.iter().filter_map(|query| query.rank).max() {
+        for rank in 0..=max_rank {
+            let mut rank_times = queries.iter().zip(&times)
+                .filter(|(query, _)| query.rank == Some(rank))
+                .map(|(_, time)| *time)
+                .collect::<Vec<_>>();
+            ranks.push((rank, Summary::of(&mut rank_times)));
+        }
+    }
+
+    BenchReport {
+        algorithm,
+        metric,
+        queue,
+        compact,
+        threads: thread_count.max(1),
+        seed,
+        wall_time,
+        total: Summary::of(&mut times),
+        ranks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{random_queries, rank_queries, BenchGraph, Summary};
+    use crate::cost::Metric;
+    use crate::Graph;
+
+    #[test]
+    fn percentiles() {
+        let mut times = (1..=100).rev().map(Duration::from_micros).collect::<Vec<_>>();
+        let summary = Summary::of(&mut times);
+
+        assert_eq!(summary.count, 100);
+        assert!((summary.mean - 50.5).abs() < 1e-9);
+        assert!((summary.median - 51.0).abs() < 1e-9);
+        assert!((summary.p99 - 99.0).abs() < 1e-9);
+        assert!((summary.max - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn empty_graph() {
+        assert!(random_queries(0, 10, 1).is_empty());
+        let graph = BenchGraph::Full(Arc::new(Graph::from_edges(Vec::new(), Vec::new())));
+        assert!(rank_queries(&graph, Metric::Cost, 10, 1).is_empty());
+    }
+}
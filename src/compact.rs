@@ -0,0 +1,225 @@
+use std::mem::size_of;
+
+use crate::coords::Coords;
+use crate::edge::{Edge, EdgeAttributes, EdgeCost};
+use crate::graph::{self, GraphSink};
+use crate::queue::{BinaryQueue, PriorityQueue, QuaternaryHeap, QueueKind, RadixHeap};
+use crate::Graph;
+
+/// A read-only graph with 32 bit ids and costs. The outgoing edges of node `i` are
+/// `offsets[i]..offsets[i + 1]`, the source of an edge is implied by that range, so only targets and
+/// costs are stored. An edge takes 8 instead of 24 bytes.
+#[derive(Default)]
+pub struct CompactGraph {
+    coords: Vec<Coords>,
+    /// One more entry than nodes, the last one is the edge count
+    offsets: Vec<u32>,
+    targets: Vec<u32>,
+    costs: Vec<u32>,
+}
+
+impl CompactGraph {
+    /// Fails if the graph has too many nodes or edges, an invalid target or a cost which doesn't fit into `u32`
+    pub fn from_graph(graph: &Graph) -> Result<CompactGraph, String> {
+        let mut builder = CompactBuilder::default();
+        builder.reserve(graph.node_count(), graph.edge_count());
+        graph.nodes().iter().for_each(|node| builder.node(node.coords));
+        for node in 0..graph.node_count() {
+            for edge in graph.outgoing_edges(node) {
+                builder.edge(edge.clone())?;
+            }
+        }
+        builder.finish()
+    }
+
+    /// Reads a graph file straight into the compact representation without building a `Graph`
+    /// first. The edges of the file have to be sorted by their source.
+    pub fn from_file(path: String) -> Result<CompactGraph, String> {
+        let mut builder = CompactBuilder::default();
+        graph::read_file(path, &mut builder)?;
+        builder.finish()
+    }
+
+    pub fn node_count(&self) -> usize { self.coords.len() }
+    pub fn edge_count(&self) -> usize { self.targets.len() }
+    pub fn coords(&self, node: usize) -> Option<&Coords> { self.coords.get(node) }
+
+    /// The targets and costs of the outgoing edges of a node
+    pub fn outgoing_edges(&self, node: usize) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let range = self.offsets[node] as usize..self.offsets[node + 1] as usize;
+        self.targets[range.clone()].iter().copied().zip(self.costs[range].iter().copied())
+    }
+
+    /// The bytes allocated by the graph
+    pub fn memory_size(&self) -> usize {
+        self.coords.capacity() * size_of::<Coords>()
+            + (self.offsets.capacity() + self.targets.capacity() + self.costs.capacity()) * size_of::<u32>()
+    }
+
+    /// The distance from `start` to `goal` using dijkstra with the given queue, -1 if the goal is unreachable
+    pub fn shortest_path(&self, start: usize, goal: usize, queue: QueueKind) -> EdgeCost {
+        let done = |node| node == goal;
+        match queue {
+            QueueKind::Binary => self.dijkstra::<BinaryQueue>(start, done),
+            QueueKind::Radix => self.dijkstra::<RadixHeap>(start, done),
+            QueueKind::Dary => self.dijkstra::<QuaternaryHeap>(start, done),
+        }
+    }
+
+    /// All nodes reachable from `start` in the order dijkstra settles them
+    pub fn settled_order(&self, start: usize) -> Vec<usize> {
+        let mut settled = Vec::new();
+        self.dijkstra::<BinaryQueue>(start, |node| {
+            settled.push(node);
+            false
+        });
+        settled
+    }
+
+    /// Stops at the first settled node for which `done` is true and returns its distance, -1 if there
+    /// is none. Distances are summed up as `EdgeCost`, so long routes can't overflow.
+    fn dijkstra<Q: PriorityQueue>(&self, start: usize, mut done: impl FnMut(usize) -> bool) -> EdgeCost {
+        let mut dist = vec![EdgeCost::MAX; self.node_count()];
+        let mut heap = Q::new(self.node_count());
+        dist[start] = 0;
+        heap.push(start, 0);
+
+        while let Some((node, cost)) = heap.pop() {
+            if cost > dist[node] {
+                continue;
+            }
+            if done(node) {
+                return cost;
+            }
+
+            for (trg, edge_cost) in self.outgoing_edges(node) {
+                let next = cost + edge_cost as EdgeCost;
+                if next < dist[trg as usize] {
+                    dist[trg as usize] = next;
+                    heap.push(trg as usize, next);
+                }
+            }
+        }
+
+        -1
+    }
+}
+
+/// Builds a `CompactGraph` from edges which are sorted by their source
+#[derive(Default)]
+struct CompactBuilder {
+    graph: CompactGraph,
+}
+
+impl GraphSink for CompactBuilder {
+    fn reserve(&mut self, node_count: usize, edge_count: usize) {
+        // Larger graphs fail on their first edge
+        let graph = &mut self.graph;
+        graph.coords.reserve(node_count.min(u32::MAX as usize));
+        graph.offsets.reserve(node_count.min(u32::MAX as usize) + 1);
+        graph.targets.reserve(edge_count.min(u32::MAX as usize));
+        graph.costs.reserve(edge_count.min(u32::MAX as usize));
+    }
+
+    fn node(&mut self, coords: Coords) {
+        self.graph.coords.push(coords);
+    }
+
+    fn elevation(&mut self, _elevation: f64) {}
+
+    fn edge(&mut self, edge: Edge) -> Result<(), String> {
+        let graph = &mut self.graph;
+        let node_count = graph.node_count();
+        if node_count > u32::MAX as usize {
+            return Err(format!("The graph has {} nodes, at most {} are supported", node_count, u32::MAX));
+        }
+        if graph.edge_count() >= u32::MAX as usize {
+            return Err(format!("The graph has more than {} edges", u32::MAX));
+        }
+        if edge.src < 0 || edge.src as usize >= node_count {
+            return Err(format!("The edge {} has a source which is not a node", edge));
+        }
+        if edge.trg < 0 || edge.trg as usize >= node_count {
+            return Err(format!("The edge {} has a target which is not a node", edge));
+        }
+        let cost = u32::try_from(edge.cost).map_err(|_| format!("The edge {} has a cost outside of 0..={}", edge, u32::MAX))?;
+        // offsets[i] is pushed with the first edge after node i - 1, so it holds the edges of all
+        // nodes before i
+        let src = edge.src as usize;
+        if src + 1 < graph.offsets.len() {
+            return Err(format!("The edge {} is not sorted by its source", edge));
+        }
+        while graph.offsets.len() <= src {
+            graph.offsets.push(graph.targets.len() as u32);
+        }
+        graph.targets.push(edge.trg as u32);
+        graph.costs.push(cost);
+        Ok(())
+    }
+
+    fn edge_attributes(&mut self, _attributes: EdgeAttributes) {}
+}
+
+impl CompactBuilder {
+    fn finish(self) -> Result<CompactGraph, String> {
+        let mut graph = self.graph;
+        if graph.node_count() > u32::MAX as usize {
+            return Err(format!("The graph has {} nodes, at most {} are supported", graph.node_count(), u32::MAX));
+        }
+        while graph.offsets.len() <= graph.node_count() {
+            graph.offsets.push(graph.targets.len() as u32);
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompactBuilder, CompactGraph};
+    use crate::coords::Coords;
+    use crate::edge::Edge;
+    use crate::generate::{fixtures, grid};
+    use crate::graph::GraphSink;
+    use crate::queue::QueueKind;
+    use crate::router;
+    use crate::Graph;
+
+    #[test]
+    fn same_distances() {
+        let graph = grid(6, 8, 50, 3);
+        let compact = CompactGraph::from_graph(&graph).unwrap();
+        assert_eq!(compact.edge_count(), graph.edge_count());
+        for (start, goal) in [(0, 47), (47, 0), (12, 30), (5, 5)] {
+            for queue in [QueueKind::Binary, QueueKind::Radix, QueueKind::Dary] {
+                assert_eq!(compact.shortest_path(start, goal, queue), router::shortest_path(&graph, start, goal));
+            }
+        }
+
+        for fixture in fixtures() {
+            let compact = CompactGraph::from_graph(&fixture.graph).unwrap();
+            for (start, goal, expected) in fixture.expected {
+                assert_eq!(compact.shortest_path(start, goal, QueueKind::Binary), expected.unwrap_or(-1), "{}", fixture.name);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_negative_costs() {
+        let graph = Graph::from_edges(vec![Coords::default(); 2], vec![Edge::new(0, 1, -3)]);
+        assert!(CompactGraph::from_graph(&graph).is_err());
+    }
+
+    #[test]
+    fn builder() {
+        let mut builder = CompactBuilder::default();
+        (0..4).for_each(|_| builder.node(Coords::default()));
+        builder.edge(Edge::new(1, 2, 5)).unwrap();
+        builder.edge(Edge::new(1, 0, 3)).unwrap();
+        assert!(builder.edge(Edge::new(2, 4, 1)).is_err());
+        assert!(builder.edge(Edge::new(0, 1, 1)).is_err());
+        let compact = builder.finish().unwrap();
+        assert_eq!(compact.offsets, vec![0, 0, 2, 2, 2]);
+        assert_eq!(compact.outgoing_edges(1).collect::<Vec<_>>(), vec![(2, 5), (0, 3)]);
+        assert_eq!(compact.settled_order(1), vec![1, 0, 2]);
+    }
+}
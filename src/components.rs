@@ -0,0 +1,155 @@
+use std::cmp::min;
+
+use crate::Graph;
+
+/// The strongly connected components of a graph
+pub struct Components {
+    /// The component of every node
+    pub component: Vec<usize>,
+    /// The amount of nodes in every component
+    pub sizes: Vec<usize>,
+}
+
+impl Components {
+    pub fn count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// The id of the component with the most nodes, `None` for an empty graph
+    pub fn largest(&self) -> Option<usize> {
+        (0..self.sizes.len()).max_by_key(|component| self.sizes[*component])
+    }
+
+    /// Marks the nodes which belong to `component`
+    pub fn members(&self, component: usize) -> Vec<bool> {
+        self.component.iter().map(|c| *c == component).collect()
+    }
+
+    /// The amount of components with 1-9, 10-99, 100-999, ... nodes
+    pub fn size_histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        for size in &self.sizes {
+            let bucket = size.to_string().len() - 1;
+            if histogram.len() <= bucket {
+                histogram.resize(bucket + 1, 0);
+            }
+            histogram[bucket] += 1;
+        }
+        histogram
+    }
+}
+
+/// Computes the strongly connected components with Tarjan's algorithm. The depth first search keeps
+/// its own stack, so long paths in road networks can't overflow the call stack.
+pub fn strongly_connected(graph: &Graph) -> Components {
+    const UNVISITED: usize = usize::MAX;
+    let node_count = graph.node_count();
+    let mut index = vec![UNVISITED; node_count];
+    let mut lowlink = vec![0; node_count];
+    let mut on_stack = vec![false; node_count];
+    let mut stack = Vec::new();
+    // The nodes of the depth first search with the next of their outgoing edges to look at
+    let mut call_stack: Vec<(usize, usize)> = Vec::new();
+    let mut next_index = 0;
+
+    let mut component = vec![UNVISITED; node_count];
+    let mut sizes = Vec::new();
+
+    for root in 0..node_count {
+        if index[root] != UNVISITED {
+            continue;
+        }
+
+        index[root] = next_index;
+        lowlink[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        call_stack.push((root, graph.outgoing_edge_range(root).start));
+
+        while let Some((node, edge_index)) = call_stack.last_mut() {
+            let node = *node;
+            if *edge_index < graph.outgoing_edge_range(node).end {
+                let trg = graph.edge(*edge_index).unwrap().trg as usize;
+                *edge_index += 1;
+
+                if index[trg] == UNVISITED {
+                    index[trg] = next_index;
+                    lowlink[trg] = next_index;
+                    next_index += 1;
+                    stack.push(trg);
+                    on_stack[trg] = true;
+                    call_stack.push((trg, graph.outgoing_edge_range(trg).start));
+                } else if on_stack[trg] {
+                    lowlink[node] = min(lowlink[node], index[trg]);
+                }
+                continue;
+            }
+
+            call_stack.pop();
+            if let Some((parent, _)) = call_stack.last() {
+                lowlink[*parent] = min(lowlink[*parent], lowlink[node]);
+            }
+
+            if lowlink[node] == index[node] {
+                let id = sizes.len();
+                let mut size = 0;
+                loop {
+                    let member = stack.pop().unwrap();
+                    on_stack[member] = false;
+                    component[member] = id;
+                    size += 1;
+                    if member == node {
+                        break;
+                    }
+                }
+                sizes.push(size);
+            }
+        }
+    }
+
+    Components { component, sizes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::strongly_connected;
+    use crate::coords::Coords;
+    use crate::edge::Edge;
+    use crate::Graph;
+
+    #[test]
+    fn components() {
+        // 0 <-> 1 -> 2 <-> 3 <-> 4, 5 isolated
+        let edges = [(0, 1), (1, 0), (1, 2), (2, 3), (3, 2), (3, 4), (4, 3)];
+        let graph = Graph::from_edges(
+            vec![Coords::default(); 6],
+            edges.iter().map(|(src, trg)| Edge::new(*src, *trg, 1)).collect(),
+        );
+        let components = strongly_connected(&graph);
+
+        assert_eq!(components.count(), 3);
+        assert_eq!(components.component[0], components.component[1]);
+        assert_eq!(components.component[2], components.component[4]);
+        assert_ne!(components.component[1], components.component[2]);
+        assert_eq!(components.sizes[components.largest().unwrap()], 3);
+        assert_eq!(components.size_histogram(), vec![3]);
+
+        let (largest, mapping) = graph.restrict_to(&components.members(components.largest().unwrap()));
+        assert_eq!(largest.node_count(), 3);
+        assert_eq!(largest.edge_count(), 4);
+        assert_eq!(mapping.to_original(0), 2);
+        assert_eq!(mapping.to_new(4), Some(2));
+        assert_eq!(mapping.to_new(1), None);
+    }
+
+    #[test]
+    fn long_path() {
+        // Deep enough to overflow the call stack of a recursive implementation
+        let count = 1_000_000;
+        let edges = (0..count - 1).flat_map(|i| [Edge::new(i, i + 1, 1), Edge::new(i + 1, i, 1)]).collect();
+        let graph = Graph::from_edges(vec![Coords::default(); count as usize], edges);
+
+        assert_eq!(strongly_connected(&graph).count(), 1);
+    }
+}
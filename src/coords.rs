@@ -1,8 +1,9 @@
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 const EARTH_RADIUS: f64 = 6371000.785f64;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Coords {
     pub lat: f64,
     pub lon: f64
@@ -47,9 +48,10 @@ impl Coords {
         let coords1r = self.to_radians();
         let coords2r = other.to_radians();
 
-        let zeta = f64::acos(
+        // Rounding can push the cosine slightly above 1 for (almost) equal coordinates
+        let zeta = f64::acos((
             f64::sin(coords1r.lat) * f64::sin(coords2r.lat) +
-                f64::cos(coords1r.lat) * f64::cos(coords2r.lat) * f64::cos(coords2r.lon - coords1r.lon));
+                f64::cos(coords1r.lat) * f64::cos(coords2r.lat) * f64::cos(coords2r.lon - coords1r.lon)).clamp(-1.0, 1.0));
 
         zeta * EARTH_RADIUS
     }
@@ -59,8 +61,128 @@ impl Coords {
         let lon_dif = other.lon - self.lon;
         f64::sqrt(lat_dif * lat_dif + lon_dif * lon_dif)
     }
+}
 
-    pub fn default() -> Self {
+impl Default for Coords {
+    fn default() -> Self {
         Self { lat: 0f64, lon: 0f64 }
     }
 }
+
+/// An axis-aligned lat/lon rectangle
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoundingBox {
+    pub min: Coords,
+    pub max: Coords,
+}
+
+impl BoundingBox {
+    pub fn new(min: Coords, max: Coords) -> Self {
+        Self { min, max }
+    }
+
+    /// The smallest box containing all coordinates, `None` if there are none
+    pub fn around<'a>(coords: impl IntoIterator<Item = &'a Coords>) -> Option<Self> {
+        let mut iter = coords.into_iter();
+        let first = *iter.next()?;
+        Some(iter.fold(Self::new(first, first), |mut bbox, coords| {
+            bbox.min.lat = bbox.min.lat.min(coords.lat);
+            bbox.min.lon = bbox.min.lon.min(coords.lon);
+            bbox.max.lat = bbox.max.lat.max(coords.lat);
+            bbox.max.lon = bbox.max.lon.max(coords.lon);
+            bbox
+        }))
+    }
+
+    /// A box containing everything within `radius` meters of `center`. If the circle reaches a pole
+    /// or the antimeridian, the box spans all longitudes.
+    pub fn around_circle(center: Coords, radius: f64) -> Self {
+        let angle = radius / EARTH_RADIUS;
+        let (min_lat, max_lat) = (center.lat - angle.to_degrees(), center.lat + angle.to_degrees());
+        // The widest point of a spherical cap is where its boundary touches a meridian
+        let lon_angle = if min_lat > -90.0 && max_lat < 90.0 {
+            (angle.sin() / center.lat.to_radians().cos()).min(1.0).asin().to_degrees()
+        } else {
+            f64::INFINITY
+        };
+        let (mut min_lon, mut max_lon) = (center.lon - lon_angle, center.lon + lon_angle);
+        if min_lon < -180.0 || max_lon > 180.0 {
+            (min_lon, max_lon) = (f64::NEG_INFINITY, f64::INFINITY);
+        }
+        Self::new(Coords::deg(min_lat, min_lon), Coords::deg(max_lat, max_lon))
+    }
+
+    pub fn contains(&self, coords: &Coords) -> bool {
+        coords.lat >= self.min.lat && coords.lat <= self.max.lat
+            && coords.lon >= self.min.lon && coords.lon <= self.max.lon
+    }
+}
+
+impl Display for BoundingBox {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.min.lat, self.min.lon, self.max.lat, self.max.lon)
+    }
+}
+
+/// A polygon with optional holes. Rings don't need to be closed, the last point connects to the first.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Polygon {
+    pub exterior: Vec<Coords>,
+    pub holes: Vec<Vec<Coords>>,
+}
+
+impl Polygon {
+    pub fn new(exterior: Vec<Coords>, holes: Vec<Vec<Coords>>) -> Self {
+        Self { exterior, holes }
+    }
+
+    pub fn bbox(&self) -> Option<BoundingBox> {
+        BoundingBox::around(&self.exterior)
+    }
+
+    /// Whether the coordinates are inside the exterior and outside of all holes. The edges are
+    /// treated as straight lines in the lat/lon plane.
+    pub fn contains(&self, coords: &Coords) -> bool {
+        if !self.bbox().map_or(false, |bbox| bbox.contains(coords)) {
+            return false;
+        }
+        // Even-odd rule, crossing a hole boundary counts just like crossing the exterior
+        std::iter::once(&self.exterior).chain(&self.holes)
+            .filter(|ring| Self::ring_crossings(ring, coords) % 2 == 1)
+            .count() % 2 == 1
+    }
+
+    /// How often a ray from the coordinates towards increasing longitude crosses the ring
+    fn ring_crossings(ring: &[Coords], coords: &Coords) -> usize {
+        let mut crossings = 0;
+        for (i, a) in ring.iter().enumerate() {
+            let b = &ring[(i + 1) % ring.len()];
+            if (a.lat > coords.lat) != (b.lat > coords.lat) {
+                let lon = a.lon + (coords.lat - a.lat) / (b.lat - a.lat) * (b.lon - a.lon);
+                if coords.lon < lon {
+                    crossings += 1;
+                }
+            }
+        }
+        crossings
+    }
+}
+
+/// Parses `<min lat>,<min lon>,<max lat>,<max lon>`
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s.split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("'{}' contains a value which is not a number", s))?;
+
+        match values[..] {
+            [min_lat, min_lon, max_lat, max_lon] if min_lat <= max_lat && min_lon <= max_lon => {
+                Ok(Self::new(Coords::deg(min_lat, min_lon), Coords::deg(max_lat, max_lon)))
+            }
+            _ => Err(format!("Expected '<min lat>,<min lon>,<max lat>,<max lon>' but got '{}'", s)),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::edge::{EdgeAttributes, EdgeCost};
+use crate::Graph;
+
+/// Typical speeds in km/h by road type for edges without a maximum speed. The road types are the
+/// ones of the FMI graph files: 1 motorway, 2 trunk, 3 primary, 4 secondary, 5 tertiary, 6 to 9 the
+/// links of the first four, 10 road, 11 unclassified, 12 residential, 13 living street and 14 service.
+const ROAD_TYPE_SPEEDS: [f64; 15] = [50.0, 120.0, 100.0, 80.0, 70.0, 60.0, 80.0, 60.0, 50.0, 50.0, 50.0, 40.0, 30.0, 10.0, 20.0];
+const DEFAULT_SPEED: f64 = 50.0;
+/// Weighted costs are stored as multiples of 1 / WEIGHTED_UNIT
+const WEIGHTED_UNIT: f64 = 1000.0;
+
+/// Computes the weight of an edge for routing, so the same graph can answer shortest distance,
+/// fastest time and custom queries. The weights must not be negative.
+pub trait CostFunction: Sync {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost;
+
+    /// Whether the edge may be used at all, e.g. motorways are closed to bicycles
+    fn allows(&self, _graph: &Graph, _edge: usize) -> bool {
+        true
+    }
+}
+
+/// The cost stored in the graph file
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct StoredCost;
+
+/// The great-circle length of the edge in meters
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Distance;
+
+/// The travel time in milliseconds at the maximum speed of the edge, or at a typical speed for its road type
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct TravelTime;
+
+/// A weighted sum of the length in meters, the travel time in seconds and the climb in meters. The
+/// cost is the sum in thousandths, so short edges don't round to zero.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct Weighted {
+    pub length: f64,
+    pub time: f64,
+    pub climb: f64,
+}
+
+impl CostFunction for StoredCost {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        graph.edges()[edge].cost
+    }
+}
+
+impl CostFunction for Distance {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        length(graph, edge).round() as EdgeCost
+    }
+}
+
+impl CostFunction for TravelTime {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        (seconds(graph, edge) * 1000.0).round() as EdgeCost
+    }
+}
+
+impl CostFunction for Weighted {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        let cost = self.length * length(graph, edge) + self.time * seconds(graph, edge) + self.climb * climb(graph, edge);
+        (cost.max(0.0) * WEIGHTED_UNIT).round() as EdgeCost
+    }
+}
+
+/// The great-circle length of an edge in meters
+pub fn length(graph: &Graph, edge: usize) -> f64 {
+    let edge = &graph.edges()[edge];
+    let (src, trg) = (graph.node(edge.src as usize).unwrap(), graph.node(edge.trg as usize).unwrap());
+    src.coords.distance_to(&trg.coords)
+}
+
+/// The speed in km/h on an edge: its maximum speed if known, otherwise the typical speed of its road type
+pub fn speed(attributes: EdgeAttributes) -> f64 {
+    if attributes.max_speed > 0 {
+        return attributes.max_speed as f64;
+    }
+    ROAD_TYPE_SPEEDS.get(attributes.road_type as usize).copied().unwrap_or(DEFAULT_SPEED)
+}
+
+/// The elevation gained along an edge in meters, zero if it goes downhill or the graph has no elevations
+pub fn climb(graph: &Graph, edge: usize) -> f64 {
+    let edge = &graph.edges()[edge];
+    match (graph.elevation(edge.src as usize), graph.elevation(edge.trg as usize)) {
+        (Some(src), Some(trg)) => (trg - src).max(0.0),
+        _ => 0.0,
+    }
+}
+
+fn seconds(graph: &Graph, edge: usize) -> f64 {
+    length(graph, edge) / (speed(graph.edge_attributes(edge)) / 3.6)
+}
+
+/// The cost functions to choose from on the command line
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum Metric {
+    #[default]
+    Cost,
+    Distance,
+    Time,
+    Weighted(Weighted),
+}
+
+impl Metric {
+    /// What a cost of this metric is called in the output, e.g. 'Travel time'
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Cost => "Cost",
+            Metric::Distance => "Distance",
+            Metric::Time => "Travel time",
+            Metric::Weighted(_) => "Weighted cost",
+        }
+    }
+
+    /// A cost of this metric for humans, e.g. '12.5 s' for travel times or '3.250' for weighted sums
+    pub fn format(&self, cost: EdgeCost) -> String {
+        match self {
+            Metric::Distance => format!("{} m", cost),
+            Metric::Time => format!("{:.1} s", cost as f64 / 1000.0),
+            Metric::Weighted(_) => format!("{:.3}", cost as f64 / WEIGHTED_UNIT),
+            Metric::Cost => cost.to_string(),
+        }
+    }
+}
+
+impl CostFunction for Metric {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        match self {
+            Metric::Cost => StoredCost.cost(graph, edge),
+            Metric::Distance => Distance.cost(graph, edge),
+            Metric::Time => TravelTime.cost(graph, edge),
+            Metric::Weighted(weighted) => weighted.cost(graph, edge),
+        }
+    }
+}
+
+impl Display for Metric {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::Cost => write!(f, "cost"),
+            Metric::Distance => write!(f, "distance"),
+            Metric::Time => write!(f, "time"),
+            Metric::Weighted(weighted) => write!(f, "weighted:length={},time={},climb={}", weighted.length, weighted.time, weighted.climb),
+        }
+    }
+}
+
+/// Parses 'cost', 'distance', 'time' or 'weighted:length=<w>,time=<w>,climb=<w>', missing weights are zero
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cost" => return Ok(Metric::Cost),
+            "distance" => return Ok(Metric::Distance),
+            "time" => return Ok(Metric::Time),
+            _ => {}
+        }
+
+        let Some(weights) = s.strip_prefix("weighted:") else {
+            return Err(format!("Unknown metric '{}', expected 'cost', 'distance', 'time' or 'weighted:<weights>'", s));
+        };
+        let mut weighted = Weighted::default();
+        for weight in weights.split(',') {
+            let (name, value) = weight.split_once('=').ok_or_else(|| format!("Expected '<name>=<weight>' but got '{}'", weight))?;
+            let value = value.trim().parse::<f64>().map_err(|_| format!("Invalid weight '{}'", value))?;
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!("The weight of '{}' must be a non-negative number", name));
+            }
+            match name.trim() {
+                "length" => weighted.length = value,
+                "time" => weighted.time = value,
+                "climb" => weighted.climb = value,
+                _ => return Err(format!("Unknown weight '{}', expected 'length', 'time' or 'climb'", name)),
+            }
+        }
+        Ok(Metric::Weighted(weighted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CostFunction, Metric, Weighted};
+    use crate::coords::Coords;
+    use crate::edge::{Edge, EdgeAttributes};
+    use crate::router::{self, SearchTrace};
+    use crate::Graph;
+
+    /// A short slow road and a longer motorway from 0 to 2, the road climbs 100m
+    fn graph() -> Graph {
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(48.01, 9.0), Coords::deg(48.02, 9.0), Coords::deg(48.01, 9.02)];
+        let edges = vec![Edge::new(0, 1, 1), Edge::new(1, 2, 1), Edge::new(0, 3, 1), Edge::new(3, 2, 1)];
+        let road = EdgeAttributes { road_type: 12, max_speed: 30 };
+        let motorway = EdgeAttributes { road_type: 1, max_speed: 0 };
+        let mut graph = Graph::from_edges_with_attributes(coords, edges, vec![road, road, motorway, motorway]);
+        graph.set_elevations(vec![200.0, 300.0, 200.0, 200.0]);
+        graph
+    }
+
+    #[test]
+    fn metrics() {
+        let graph = graph();
+        let route = |metric: Metric| {
+            let mut trace = SearchTrace::default().with_pred();
+            let cost = router::shortest_path_with(&graph, &metric, 0, 2, &mut trace);
+            (cost, trace.path_to(&graph, 0, 2).nodes())
+        };
+
+        // About 1112m per 0.01 degrees of latitude
+        let (distance, nodes) = route(Metric::Distance);
+        assert!((2200..2250).contains(&distance));
+        assert_eq!(nodes, vec![0, 1, 2]);
+        let (time, nodes) = route(Metric::Time);
+        assert_eq!(nodes, vec![0, 3, 2]);
+        // The edges are sorted by source, so the motorway edges are 1 and 3
+        assert_eq!(time, Metric::Time.cost(&graph, 1) + Metric::Time.cost(&graph, 3));
+        assert_eq!(route(Metric::Cost).0, 2);
+        assert_eq!(route(Metric::Weighted(Weighted { length: 1.0, time: 0.0, climb: 100.0 })).1, vec![0, 3, 2]);
+        // Less than a second is not rounded away
+        let weighted = Metric::Weighted(Weighted { length: 0.0, time: 1.0, climb: 0.0 });
+        let (cost, _) = route(weighted);
+        assert!((cost - time).abs() <= 2, "{} {}", cost, time);
+        assert_eq!(weighted.format(1500), "1.500");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("time".parse::<Metric>(), Ok(Metric::Time));
+        let metric = "weighted:length=1,climb=2.5".parse::<Metric>().unwrap();
+        assert_eq!(metric, Metric::Weighted(Weighted { length: 1.0, time: 0.0, climb: 2.5 }));
+        assert_eq!(metric.to_string().parse::<Metric>(), Ok(metric));
+        assert!("weighted:speed=1".parse::<Metric>().is_err());
+        assert!("weighted:time=-1".parse::<Metric>().is_err());
+        assert!("weighted:time=inf".parse::<Metric>().is_err());
+        assert!("weighted:length=NaN".parse::<Metric>().is_err());
+        assert!("fastest".parse::<Metric>().is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::thread;
+
+use crate::cost::CostFunction;
+use crate::edge::EdgeCost;
+use crate::router::Direction;
+use crate::Graph;
+
+/// Frontiers smaller than this are relaxed on the calling thread, spawning threads would take longer
+const PARALLEL_THRESHOLD: usize = 1024;
+
+/// A bucket width which works well for road networks: the mean edge cost
+pub fn default_delta(graph: &Graph, costs: &dyn CostFunction) -> EdgeCost {
+    let total = (0..graph.edge_count()).map(|edge| costs.cost(graph, edge).max(0) as i128).sum::<i128>();
+    (total / graph.edge_count().max(1) as i128).max(1) as EdgeCost
+}
+
+/// Parallel one-to-all shortest paths with delta-stepping. Nodes are kept in buckets of width
+/// `delta` by their tentative distance, and all nodes of the lowest bucket are relaxed at the same
+/// time by `thread_count` threads. Returns the same distances as `router::shortest_paths`, or as
+/// `router::shortest_paths_to` for backward searches, i.e. `EdgeCost::MAX` for unreachable nodes.
+/// Edges are weighed by `costs`, which must not be negative.
+pub fn shortest_paths(graph: &Graph, costs: &dyn CostFunction, start: usize, direction: Direction, delta: EdgeCost, thread_count: usize) -> Result<Vec<EdgeCost>, String> {
+    if let Some(edge) = (0..graph.edge_count()).find(|edge| costs.cost(graph, *edge) < 0) {
+        return Err(format!("Delta-stepping doesn't support negative costs like the edge {}", graph.edges()[edge]));
+    }
+
+    let delta = delta.max(1);
+    let dist = (0..graph.node_count()).map(|_| AtomicI64::new(EdgeCost::MAX)).collect::<Vec<_>>();
+    // Only non-empty buckets are kept, so a tiny delta doesn't allocate one bucket per distance
+    let mut buckets: BTreeMap<EdgeCost, Vec<usize>> = BTreeMap::from([(0, vec![start])]);
+    dist[start].store(0, Ordering::Relaxed);
+
+    while let Some(&index) = buckets.keys().next() {
+        // Light edges can put nodes back into the current bucket, so it is emptied repeatedly.
+        // Heavy edges always lead to later buckets and are relaxed once for all nodes of the bucket.
+        let mut settled = Vec::new();
+        while let Some(mut frontier) = buckets.remove(&index) {
+            frontier.sort_unstable();
+            frontier.dedup();
+            frontier.retain(|node| dist[*node].load(Ordering::Relaxed) / delta == index);
+
+            let improved = relax(graph, costs, direction, &dist, &frontier, |cost| cost <= delta, thread_count);
+            settled.extend(frontier);
+            insert(&mut buckets, &dist, improved, delta);
+        }
+
+        settled.sort_unstable();
+        settled.dedup();
+        let improved = relax(graph, costs, direction, &dist, &settled, |cost| cost > delta, thread_count);
+        insert(&mut buckets, &dist, improved, delta);
+    }
+
+    Ok(dist.into_iter().map(AtomicI64::into_inner).collect())
+}
+
+/// Relaxes the edges of `nodes` for which `is_relaxed` holds and returns the targets whose distance improved
+fn relax(graph: &Graph, costs: &dyn CostFunction, direction: Direction, dist: &[AtomicI64], nodes: &[usize], is_relaxed: impl Fn(EdgeCost) -> bool + Sync, thread_count: usize) -> Vec<usize> {
+    let relax_chunk = |chunk: &[usize]| {
+        let mut improved = Vec::new();
+        for node in chunk {
+            let node_dist = dist[*node].load(Ordering::Relaxed);
+            for position in direction.edge_range(graph, *node) {
+                let edge_index = direction.edge_index(position);
+                if !costs.allows(graph, edge_index) {
+                    continue;
+                }
+                let edge_cost = costs.cost(graph, edge_index);
+                if !is_relaxed(edge_cost) {
+                    continue;
+                }
+                let (trg, next) = (direction.next(&graph.edges()[edge_index]), node_dist.saturating_add(edge_cost));
+                if next < dist[trg].fetch_min(next, Ordering::Relaxed) {
+                    improved.push(trg);
+                }
+            }
+        }
+        improved
+    };
+
+    if thread_count <= 1 || nodes.len() < PARALLEL_THRESHOLD {
+        return relax_chunk(nodes);
+    }
+
+    let chunk_size = (nodes.len() + thread_count - 1) / thread_count;
+    thread::scope(|scope| {
+        let handles = nodes.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || relax_chunk(chunk)))
+            .collect::<Vec<_>>();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+fn insert(buckets: &mut BTreeMap<EdgeCost, Vec<usize>>, dist: &[AtomicI64], nodes: Vec<usize>, delta: EdgeCost) {
+    for node in nodes {
+        let bucket = dist[node].load(Ordering::Relaxed) / delta;
+        buckets.entry(bucket).or_default().push(node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_delta, shortest_paths};
+    use crate::coords::Coords;
+    use crate::cost::{Metric, StoredCost};
+    use crate::edge::{Edge, EdgeCost};
+    use crate::generate::{fixtures, grid, random_geometric};
+    use crate::graph::IncomingEdges;
+    use crate::router::{self, Direction};
+    use crate::Graph;
+
+    #[test]
+    fn matches_dijkstra() {
+        // Large enough that frontiers are relaxed by several threads
+        let graphs = [grid(120, 120, 50, 1), random_geometric(3000, 0.05, 2)];
+        for graph in &graphs {
+            for start in [0, graph.node_count() / 3] {
+                let expected = router::shortest_paths(graph, start);
+                let incoming = IncomingEdges::new(graph);
+                let expected_to = router::shortest_paths_to(graph, &incoming, start);
+                for delta in [1, default_delta(graph, &StoredCost), 100_000] {
+                    assert_eq!(shortest_paths(graph, &StoredCost, start, Direction::Forward, delta, 4).unwrap(), expected);
+                    assert_eq!(shortest_paths(graph, &StoredCost, start, Direction::Backward(&incoming), delta, 4).unwrap(), expected_to);
+                }
+            }
+        }
+
+        for fixture in fixtures() {
+            for start in 0..fixture.graph.node_count() {
+                assert_eq!(shortest_paths(&fixture.graph, &StoredCost, start, Direction::Forward, 2, 2).unwrap(), router::shortest_paths(&fixture.graph, start), "{}", fixture.name);
+            }
+        }
+    }
+
+    #[test]
+    fn large_costs() {
+        // A tiny delta for huge distances only creates the buckets which are used
+        let coords = vec![Coords::default(); 3];
+        let graph = Graph::from_edges(coords.clone(), vec![Edge::new(0, 1, 1 << 40), Edge::new(1, 2, EdgeCost::MAX - 1)]);
+        assert_eq!(shortest_paths(&graph, &StoredCost, 0, Direction::Forward, 1, 2).unwrap(), vec![0, 1 << 40, EdgeCost::MAX]);
+
+        let graph = Graph::from_edges(coords, vec![Edge::new(0, 1, 4), Edge::new(1, 2, -3)]);
+        assert!(shortest_paths(&graph, &StoredCost, 0, Direction::Forward, 1, 2).is_err());
+        // Other metrics don't look at the stored costs
+        assert!(shortest_paths(&graph, &Metric::Distance, 0, Direction::Forward, 1, 2).is_ok());
+    }
+}
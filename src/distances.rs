@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+use crate::edge::EdgeCost;
+use crate::graph::{read_i64, read_u64};
+use crate::Graph;
+
+/// Magic bytes at the start of a distance file, the last byte is the format version
+const DISTANCE_MAGIC: [u8; 8] = *b"RDIST\0\0\x01";
+const NO_PREDECESSOR: u64 = u64::MAX;
+
+/// The result of a one-to-all search which can be saved and queried later without the graph
+pub struct DistanceTable {
+    /// The source, or the target of an all-to-one search
+    pub source: usize,
+    /// Whether the distances lead to `source` instead of from it
+    pub reverse: bool,
+    /// The distance of every node, `EdgeCost::MAX` if it is unreachable
+    pub dist: Vec<EdgeCost>,
+    /// The neighbour of every node on its shortest path towards `source`, `usize::MAX` for the source and unreachable nodes
+    pub pred: Option<Vec<usize>>,
+}
+
+impl DistanceTable {
+    /// Converts the edge predecessors recorded by dijkstra into node predecessors. For all-to-one
+    /// searches the predecessors are the next nodes towards `source`.
+    pub fn new(graph: &Graph, source: usize, reverse: bool, dist: Vec<EdgeCost>, pred_edges: Option<Vec<usize>>) -> Self {
+        let pred = pred_edges.map(|edges| edges.iter()
+            .map(|edge| graph.edge(*edge).map_or(usize::MAX, |edge| if reverse { edge.trg } else { edge.src } as usize))
+            .collect());
+        Self { source, reverse, dist, pred }
+    }
+
+    /// The distance to a node, `None` if it is unreachable or not a node
+    pub fn distance(&self, node: usize) -> Option<EdgeCost> {
+        self.dist.get(node).copied().filter(|dist| *dist != EdgeCost::MAX)
+    }
+
+    /// The nodes of the shortest path between `source` and a node, in the direction of the search:
+    /// from `source` to the node, or from the node to `source` for all-to-one searches. `None` if the
+    /// predecessors weren't saved or the node is unreachable.
+    pub fn path(&self, node: usize) -> Option<Vec<usize>> {
+        let pred = self.pred.as_ref()?;
+        self.distance(node)?;
+        let mut path = vec![node];
+        while path[path.len() - 1] != self.source {
+            // A broken file could hold a cycle, no path is longer than the node count
+            let next = *pred.get(path[path.len() - 1])?;
+            if next == usize::MAX || path.len() > self.dist.len() {
+                return None;
+            }
+            path.push(next);
+        }
+        if !self.reverse {
+            path.reverse();
+        }
+        Some(path)
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&DISTANCE_MAGIC)?;
+        writer.write_all(&(self.source as u64).to_le_bytes())?;
+        writer.write_all(&(self.dist.len() as u64).to_le_bytes())?;
+        writer.write_all(&[self.pred.is_some() as u8 | (self.reverse as u8) << 1])?;
+        for dist in &self.dist {
+            writer.write_all(&dist.to_le_bytes())?;
+        }
+        for pred in self.pred.iter().flatten() {
+            let pred = if *pred == usize::MAX { NO_PREDECESSOR } else { *pred as u64 };
+            writer.write_all(&pred.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<DistanceTable> {
+        let mut magic = [0u8; DISTANCE_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if magic != DISTANCE_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a distance file."));
+        }
+
+        let source = read_u64(reader)? as usize;
+        let node_count = read_u64(reader)? as usize;
+        let mut flags = [0u8];
+        reader.read_exact(&mut flags)?;
+        let dist = (0..node_count).map(|_| read_i64(reader)).collect::<io::Result<Vec<_>>>()?;
+        let pred = if flags[0] & 1 != 0 {
+            Some((0..node_count)
+                .map(|_| read_u64(reader).map(|pred| if pred == NO_PREDECESSOR { usize::MAX } else { pred as usize }))
+                .collect::<io::Result<Vec<_>>>()?)
+        } else {
+            None
+        };
+        Ok(DistanceTable { source, reverse: flags[0] & 2 != 0, dist, pred })
+    }
+
+    pub fn write_file(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn read_file(path: &str) -> io::Result<DistanceTable> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Writes `node_id,lat,lon,distance` for every node, the distance is empty for unreachable nodes
+    pub fn write_csv(&self, graph: &Graph, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "node_id,lat,lon,distance")?;
+        for (node, coords) in graph.nodes().iter().map(|node| node.coords).enumerate() {
+            match self.distance(node) {
+                Some(dist) => writeln!(writer, "{},{},{},{}", node, coords.lat, coords.lon, dist)?,
+                None => writeln!(writer, "{},{},{},", node, coords.lat, coords.lon)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DistanceTable;
+    use crate::coords::Coords;
+    use crate::cost::StoredCost;
+    use crate::edge::Edge;
+    use crate::generate::fixtures;
+    use crate::graph::IncomingEdges;
+    use crate::router::{self, Direction, SearchTrace};
+    use crate::Graph;
+
+    #[test]
+    fn round_trip() {
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(48.1, 9.0), Coords::deg(48.1, 9.1)];
+        let graph = Graph::from_edges(coords, vec![Edge::new(0, 1, 5), Edge::new(1, 0, 5)]);
+        let mut trace = SearchTrace::default().with_pred();
+        let dist = router::shortest_paths_traced(&graph, 1, &mut trace);
+        let table = DistanceTable::new(&graph, 1, false, dist, trace.pred);
+        assert_eq!(table.pred, Some(vec![1, usize::MAX, usize::MAX]));
+
+        let mut bytes = Vec::new();
+        table.write(&mut bytes).unwrap();
+        let read = DistanceTable::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!((read.source, read.reverse), (1, false));
+        assert_eq!((read.distance(0), read.distance(1), read.distance(2)), (Some(5), Some(0), None));
+        assert_eq!(read.pred, table.pred);
+        assert_eq!(read.path(0), Some(vec![1, 0]));
+        assert_eq!(read.path(1), Some(vec![1]));
+        assert_eq!(read.path(2), None);
+        assert!(DistanceTable::read(&mut &bytes[..bytes.len() - 1]).is_err());
+
+        let mut csv = Vec::new();
+        read.write_csv(&graph, &mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "node_id,lat,lon,distance\n0,48,9,5\n1,48.1,9,0\n2,48.1,9.1,\n");
+    }
+
+    #[test]
+    fn reverse_path() {
+        // 0 -> 1 -> 2 -> 3 with a more expensive shortcut from 0 to 3
+        let graph = fixtures().remove(0).graph;
+        let incoming = IncomingEdges::new(&graph);
+        let tree = router::shortest_path_tree_with(&graph, &StoredCost, 3, Direction::Backward(&incoming));
+        let table = DistanceTable::new(&graph, 3, true, tree.dist, Some(tree.parent_edges));
+
+        let mut bytes = Vec::new();
+        table.write(&mut bytes).unwrap();
+        let read = DistanceTable::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.path(0), Some(vec![0, 1, 2, 3]));
+        assert_eq!(read.path(3), Some(vec![3]));
+
+        let without = DistanceTable::new(&graph, 3, true, read.dist.clone(), None);
+        assert_eq!(without.path(0), None);
+    }
+}
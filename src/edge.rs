@@ -7,7 +7,20 @@ pub struct EdgePath {
     pub path: Vec<Edge>
 }
 
-#[derive(Clone)]
+impl EdgePath {
+    pub fn cost(&self) -> EdgeCost {
+        self.path.iter().map(|edge| edge.cost).sum()
+    }
+
+    /// The nodes along the path, starting with the source of the first edge
+    pub fn nodes(&self) -> Vec<NodeIndex> {
+        self.path.first().map(|edge| edge.src).into_iter()
+            .chain(self.path.iter().map(|edge| edge.trg))
+            .collect()
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct Edge {
     pub src: NodeIndex,
     pub trg: NodeIndex,
@@ -25,8 +38,13 @@ impl Edge {
     pub fn new(src: NodeIndex, trg: NodeIndex, cost: EdgeCost) -> Self {
         Self { src, trg, cost }
     }
+}
 
-    pub fn default() -> Self {
-        Self { src: 0, trg: 0, cost: 0 }
-    }
-}
\ No newline at end of file
+/// The attributes of an edge in the FMI file besides its cost, used by cost functions
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct EdgeAttributes {
+    /// The road type, e.g. 1 for motorways
+    pub road_type: u8,
+    /// The maximum speed in km/h, 0 if unknown
+    pub max_speed: u16,
+}
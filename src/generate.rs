@@ -0,0 +1,188 @@
+use std::str::FromStr;
+
+use crate::coords::Coords;
+use crate::edge::{Edge, EdgeCost};
+use crate::node::NodeIndex;
+use crate::rng::Rng;
+use crate::Graph;
+
+/// Where generated graphs are placed, the spacing of grid nodes in degrees
+const ORIGIN: Coords = Coords { lat: 48.0, lon: 9.0 };
+const GRID_SPACING: f64 = 0.001;
+
+/// The kinds of graphs the `generate` command can write
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Generator {
+    Grid,
+    Geometric,
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(Generator::Grid),
+            "geometric" => Ok(Generator::Geometric),
+            _ => Err(format!("Unknown generator '{}', expected 'grid' or 'geometric'", s)),
+        }
+    }
+}
+
+impl Generator {
+    /// A graph of this kind with about `node_count` nodes
+    pub fn generate(&self, node_count: usize, max_cost: EdgeCost, seed: u64) -> Graph {
+        match self {
+            Generator::Grid => {
+                let side = (node_count as f64).sqrt().ceil() as usize;
+                grid(side, side, max_cost, seed)
+            }
+            Generator::Geometric => {
+                // Connects about 6 neighbours per node in the unit square
+                let radius = (6.0 / (std::f64::consts::PI * node_count.max(1) as f64)).sqrt();
+                random_geometric(node_count, radius, seed)
+            }
+        }
+    }
+}
+
+/// A `rows` x `cols` grid where neighbours are connected in both directions. Both directions get
+/// their own random cost in `1..=max_cost`.
+pub fn grid(rows: usize, cols: usize, max_cost: EdgeCost, seed: u64) -> Graph {
+    let mut rng = Rng::new(seed);
+    let index = |row: usize, col: usize| (row * cols + col) as NodeIndex;
+    let coords = (0..rows * cols)
+        .map(|i| Coords::deg(ORIGIN.lat + (i / cols) as f64 * GRID_SPACING, ORIGIN.lon + (i % cols) as f64 * GRID_SPACING))
+        .collect();
+
+    let mut edges = Vec::new();
+    let mut cost = || 1 + rng.below(max_cost.max(1) as u64) as EdgeCost;
+    for row in 0..rows {
+        for col in 0..cols {
+            if col + 1 < cols {
+                edges.push(Edge::new(index(row, col), index(row, col + 1), cost()));
+                edges.push(Edge::new(index(row, col + 1), index(row, col), cost()));
+            }
+            if row + 1 < rows {
+                edges.push(Edge::new(index(row, col), index(row + 1, col), cost()));
+                edges.push(Edge::new(index(row + 1, col), index(row, col), cost()));
+            }
+        }
+    }
+
+    Graph::from_edges(coords, edges)
+}
+
+/// `count` random points in a one degree square, connected in both directions to all points
+/// within `radius` degrees. The cost is the distance in meters.
+pub fn random_geometric(count: usize, radius: f64, seed: u64) -> Graph {
+    let mut rng = Rng::new(seed);
+    let mut coords = (0..count)
+        .map(|_| Coords::deg(ORIGIN.lat + rng.next_f64(), ORIGIN.lon + rng.next_f64()))
+        .collect::<Vec<_>>();
+    // Sorted by latitude, so only a window of points has to be compared
+    coords.sort_by(|a, b| a.lat.total_cmp(&b.lat));
+
+    let mut edges = Vec::new();
+    for (i, a) in coords.iter().enumerate() {
+        for (j, b) in coords.iter().enumerate().skip(i + 1) {
+            if b.lat - a.lat > radius {
+                break;
+            }
+            if a.euclidean_distance_to(b) <= radius {
+                let cost = a.distance_to(b).round() as EdgeCost;
+                edges.push(Edge::new(i as NodeIndex, j as NodeIndex, cost));
+                edges.push(Edge::new(j as NodeIndex, i as NodeIndex, cost));
+            }
+        }
+    }
+
+    Graph::from_edges(coords, edges)
+}
+
+/// A small hand-made graph with the answers to some queries, `None` if the goal is unreachable
+pub struct Fixture {
+    pub name: &'static str,
+    pub graph: Graph,
+    pub expected: Vec<(usize, usize, Option<EdgeCost>)>,
+}
+
+impl Fixture {
+    fn new(name: &'static str, node_count: usize, edges: &[(NodeIndex, NodeIndex, EdgeCost)], expected: Vec<(usize, usize, Option<EdgeCost>)>) -> Self {
+        let coords = (0..node_count).map(|i| Coords::deg(ORIGIN.lat, ORIGIN.lon + i as f64 * GRID_SPACING)).collect();
+        let edges = edges.iter().map(|(src, trg, cost)| Edge::new(*src, *trg, *cost)).collect();
+        Self { name, graph: Graph::from_edges(coords, edges), expected }
+    }
+}
+
+/// Graphs covering the corner cases: detours, one-way edges, zero costs, self-loops, parallel edges and isolated nodes
+pub fn fixtures() -> Vec<Fixture> {
+    vec![
+        Fixture::new("line with expensive shortcut", 4,
+            &[(0, 1, 1), (1, 2, 1), (2, 3, 1), (0, 3, 5)],
+            vec![(0, 3, Some(3)), (1, 3, Some(2)), (3, 0, None)]),
+        Fixture::new("diamond", 4,
+            &[(0, 1, 1), (1, 3, 10), (0, 2, 4), (2, 3, 1), (3, 1, 2)],
+            vec![(0, 3, Some(5)), (3, 0, None), (2, 1, Some(3))]),
+        Fixture::new("self-loop and parallel edges", 3,
+            &[(0, 0, 1), (0, 1, 3), (0, 1, 2), (1, 2, 0)],
+            vec![(0, 2, Some(2)), (0, 0, Some(0)), (2, 0, None)]),
+        Fixture::new("isolated node", 3,
+            &[(0, 1, 7), (1, 0, 7)],
+            vec![(1, 0, Some(7)), (0, 2, None), (2, 2, Some(0))]),
+        Fixture::new("detour", 4,
+            &[(0, 1, 5), (0, 3, 2), (1, 2, 1), (3, 2, 10), (3, 1, 1)],
+            vec![(0, 2, Some(4)), (2, 0, None), (3, 2, Some(2))]),
+    ]
+}
+
+/// The graph of the fixture with this name
+pub fn fixture(name: &str) -> Graph {
+    fixtures().into_iter().find(|fixture| fixture.name == name).expect("There is no fixture of this name").graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fixtures, grid, random_geometric};
+    use crate::edge::EdgeCost;
+    use crate::router;
+    use crate::validate::validate;
+    use crate::Graph;
+
+    /// Bellman-Ford as slow but obviously correct reference
+    fn reference_distances(graph: &Graph, start: usize) -> Vec<EdgeCost> {
+        let mut dist = vec![EdgeCost::MAX; graph.node_count()];
+        dist[start] = 0;
+        for _ in 0..graph.node_count() {
+            for edge in graph.edges() {
+                let src = dist[edge.src as usize];
+                if src != EdgeCost::MAX && src + edge.cost < dist[edge.trg as usize] {
+                    dist[edge.trg as usize] = src + edge.cost;
+                }
+            }
+        }
+        dist
+    }
+
+    #[test]
+    fn generated_graphs() {
+        for seed in 0..5 {
+            for graph in [grid(7, 9, 20, seed), random_geometric(80, 0.15, seed)] {
+                assert!(validate(&graph).is_empty());
+                for start in [0, graph.node_count() / 2, graph.node_count() - 1] {
+                    assert_eq!(router::shortest_paths(&graph, start), reference_distances(&graph, start));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn known_paths() {
+        for fixture in fixtures() {
+            for (start, goal, expected) in &fixture.expected {
+                let cost = router::shortest_path(&fixture.graph, *start, *goal);
+                assert_eq!((cost >= 0).then_some(cost), *expected, "{}: {} -> {}", fixture.name, start, goal);
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+use serde_json::{json, Value};
+
+use crate::coords::{BoundingBox, Coords, Polygon};
+use crate::edge::EdgePath;
+use crate::router::ShortestPathTree;
+use crate::Graph;
+
+/// GeoJSON positions are `[longitude, latitude]`
+fn position(coords: &Coords) -> Value {
+    json!([coords.lon, coords.lat])
+}
+
+fn node_coords(graph: &Graph, index: usize) -> &Coords {
+    &graph.node(index).expect("Node of the path is not part of the graph").coords
+}
+
+pub fn feature_collection(features: Vec<Value>) -> Value {
+    json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+/// A route as a LineString with its cost. An empty route has no geometry.
+pub fn route_feature(graph: &Graph, path: &EdgePath) -> Value {
+    let nodes = path.nodes();
+    let geometry = if nodes.is_empty() {
+        Value::Null
+    } else {
+        json!({
+            "type": "LineString",
+            "coordinates": nodes.iter().map(|node| position(node_coords(graph, *node as usize))).collect::<Vec<_>>(),
+        })
+    };
+
+    json!({
+        "type": "Feature",
+        "geometry": geometry,
+        "properties": {
+            "cost": path.cost(),
+            "source": nodes.first(),
+            "target": nodes.last(),
+        },
+    })
+}
+
+pub fn node_feature(graph: &Graph, index: usize) -> Value {
+    json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": position(node_coords(graph, index)) },
+        "properties": { "node": index },
+    })
+}
+
+/// The queried location and the node it was snapped to as two Points
+pub fn snapped_features(graph: &Graph, query: Coords, snapped: usize) -> Vec<Value> {
+    let mut node = node_feature(graph, snapped);
+    node["properties"]["kind"] = json!("snapped");
+    node["properties"]["distance"] = json!(query.distance_to(node_coords(graph, snapped)));
+
+    vec![
+        json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": position(&query) },
+            "properties": { "kind": "query" },
+        }),
+        node,
+    ]
+}
+
+/// All edges starting inside the box as LineStrings with their cost
+pub fn edges_in_bbox(graph: &Graph, bbox: &BoundingBox) -> Value {
+    let mut features = Vec::new();
+    for (src, node) in graph.nodes().iter().enumerate() {
+        if !bbox.contains(&node.coords) {
+            continue;
+        }
+
+        for edge in graph.outgoing_edges(src) {
+            features.push(json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": [position(&node.coords), position(node_coords(graph, edge.trg as usize))],
+                },
+                "properties": { "src": edge.src, "trg": edge.trg, "cost": edge.cost },
+            }));
+        }
+    }
+
+    feature_collection(features)
+}
+
+/// Every edge of the shortest-path tree as LineString with the distance of the node it belongs to,
+/// i.e. its target or, in a reverse tree, its source
+pub fn tree_features(graph: &Graph, tree: &ShortestPathTree) -> Value {
+    let features = tree.parent_edges.iter()
+        .filter_map(|edge| graph.edge(*edge))
+        .map(|edge| {
+            let (node, parent) = if tree.reverse { (edge.src, edge.trg) } else { (edge.trg, edge.src) };
+            json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": [position(node_coords(graph, edge.src as usize)), position(node_coords(graph, edge.trg as usize))],
+                },
+                "properties": { "node": node, "parent": parent, "distance": tree.dist[node as usize] },
+            })
+        })
+        .collect();
+
+    feature_collection(features)
+}
+
+fn parse_ring(value: &Value) -> Result<Vec<Coords>, String> {
+    value.as_array().ok_or("A polygon ring is not an array")?
+        .iter()
+        .map(|position| match position.as_array().map(|values| values.iter().map(Value::as_f64).collect::<Vec<_>>()) {
+            Some(values) if values.len() >= 2 && values[0].is_some() && values[1].is_some() =>
+                Ok(Coords::deg(values[1].unwrap(), values[0].unwrap())),
+            _ => Err(format!("Invalid position {}", position)),
+        })
+        .collect()
+}
+
+fn parse_polygon(value: &Value) -> Result<Polygon, String> {
+    let rings = value.as_array().ok_or("Polygon coordinates are not an array")?
+        .iter()
+        .map(parse_ring)
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut rings = rings.into_iter();
+    let exterior = rings.next().filter(|ring| ring.len() >= 3).ok_or("A polygon needs an exterior ring with at least 3 positions")?;
+    Ok(Polygon::new(exterior, rings.collect()))
+}
+
+/// Collects the polygons of a Polygon, MultiPolygon, Feature or FeatureCollection. Other geometries are skipped.
+pub fn polygons(value: &Value) -> Result<Vec<Polygon>, String> {
+    match value["type"].as_str() {
+        Some("Polygon") => Ok(vec![parse_polygon(&value["coordinates"])?]),
+        Some("MultiPolygon") => value["coordinates"].as_array().ok_or("MultiPolygon coordinates are not an array")?
+            .iter()
+            .map(parse_polygon)
+            .collect(),
+        Some("Feature") => polygons(&value["geometry"]),
+        Some("FeatureCollection") => Ok(value["features"].as_array().ok_or("The features are not an array")?
+            .iter()
+            .map(polygons)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Reads all polygons of a GeoJSON file, it's an error if there are none
+pub fn read_polygons(path: &str) -> Result<Vec<Polygon>, String> {
+    let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let value = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+    let polygons = polygons(&value)?;
+    if polygons.is_empty() {
+        return Err(format!("{} doesn't contain a polygon", path));
+    }
+    Ok(polygons)
+}
+
+pub fn write_file(path: &str, value: &Value) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{edges_in_bbox, polygons, route_feature};
+    use crate::coords::{BoundingBox, Coords};
+    use crate::edge::{Edge, EdgePath};
+    use crate::Graph;
+
+    fn graph() -> Graph {
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(48.1, 9.0), Coords::deg(48.1, 9.1)];
+        Graph::from_edges(coords, vec![Edge::new(0, 1, 5), Edge::new(1, 2, 3), Edge::new(2, 0, 1)])
+    }
+
+    #[test]
+    fn route_line_string() {
+        let graph = graph();
+        let path = EdgePath { path: vec![Edge::new(0, 1, 5), Edge::new(1, 2, 3)] };
+        let feature = route_feature(&graph, &path);
+
+        assert_eq!(feature["properties"]["cost"], 8);
+        assert_eq!(feature["geometry"]["coordinates"], serde_json::json!([[9.0, 48.0], [9.0, 48.1], [9.1, 48.1]]));
+    }
+
+    #[test]
+    fn bbox_edges() {
+        let graph = graph();
+        let bbox = BoundingBox::new(Coords::deg(48.05, 8.0), Coords::deg(49.0, 9.05));
+        let collection = edges_in_bbox(&graph, &bbox);
+
+        let features = collection["features"].as_array().unwrap();
+        assert_eq!(features.len(), 1);
+        assert_eq!(features[0]["properties"]["src"], 1);
+    }
+
+    #[test]
+    fn polygon_with_hole() {
+        let feature = serde_json::json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": [
+                    [[9.0, 48.0], [10.0, 48.0], [10.0, 49.0], [9.0, 49.0], [9.0, 48.0]],
+                    [[9.4, 48.4], [9.6, 48.4], [9.6, 48.6], [9.4, 48.6]],
+                ],
+            },
+        });
+        let parsed = polygons(&feature).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert!(parsed[0].contains(&Coords::deg(48.2, 9.2)));
+        assert!(!parsed[0].contains(&Coords::deg(48.5, 9.5)));
+        assert!(!parsed[0].contains(&Coords::deg(48.5, 10.5)));
+        assert!(polygons(&serde_json::json!({ "type": "Polygon", "coordinates": [[[9.0, 48.0]]] })).is_err());
+    }
+}
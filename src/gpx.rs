@@ -0,0 +1,87 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+
+use crate::edge::EdgePath;
+use crate::Graph;
+
+const HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gpx version="1.1" creator="router" xmlns="http://www.topografix.com/GPX/1/1">
+"#;
+const FOOTER: &str = "</gpx>\n";
+
+/// Writes the position of a node as the attributes and the (optional) elevation child of a point element
+fn point(out: &mut String, graph: &Graph, tag: &str, index: usize, name: Option<&str>) {
+    let coords = graph.node(index).expect("Node of the path is not part of the graph").coords;
+    write!(out, r#"<{} lat="{}" lon="{}">"#, tag, coords.lat, coords.lon).unwrap();
+    if let Some(elevation) = graph.elevation(index) {
+        write!(out, "<ele>{}</ele>", elevation).unwrap();
+    }
+    if let Some(name) = name {
+        write!(out, "<name>{}</name>", name).unwrap();
+    }
+    writeln!(out, "</{}>", tag).unwrap();
+}
+
+fn track(out: &mut String, graph: &Graph, name: &str, nodes: &[usize]) {
+    writeln!(out, "  <trk><name>{}</name><trkseg>", name).unwrap();
+    for node in nodes {
+        out.push_str("    ");
+        point(out, graph, "trkpt", *node, None);
+    }
+    out.push_str("  </trkseg></trk>\n");
+}
+
+fn path_nodes(path: &EdgePath, start: usize) -> Vec<usize> {
+    match path.nodes() {
+        nodes if nodes.is_empty() => vec![start],
+        nodes => nodes.into_iter().map(|node| node as usize).collect(),
+    }
+}
+
+/// A GPX document with the route as track and the snapped start and end as waypoints
+pub fn route(graph: &Graph, path: &EdgePath, start: usize, goal: usize) -> String {
+    let mut out = String::from(HEADER);
+    out.push_str("  ");
+    point(&mut out, graph, "wpt", start, Some("Start"));
+    out.push_str("  ");
+    point(&mut out, graph, "wpt", goal, Some("End"));
+    track(&mut out, graph, &format!("{} -> {}", start, goal), &path_nodes(path, start));
+    out.push_str(FOOTER);
+    out
+}
+
+/// A GPX document with one track per route
+pub fn routes<'a>(graph: &Graph, paths: impl IntoIterator<Item = &'a EdgePath>) -> String {
+    let mut out = String::from(HEADER);
+    for path in paths.into_iter().filter(|path| !path.path.is_empty()) {
+        let nodes = path.nodes().into_iter().map(|node| node as usize).collect::<Vec<_>>();
+        track(&mut out, graph, &format!("{} -> {}", nodes[0], nodes[nodes.len() - 1]), &nodes);
+    }
+    out.push_str(FOOTER);
+    out
+}
+
+pub fn write_file(path: &str, gpx: &str) -> io::Result<()> {
+    fs::write(path, gpx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::route;
+    use crate::coords::Coords;
+    use crate::edge::{Edge, EdgePath};
+    use crate::Graph;
+
+    #[test]
+    fn track_with_elevation() {
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(48.1, 9.0)];
+        let mut graph = Graph::from_edges(coords, vec![Edge::new(0, 1, 5)]);
+        graph.set_elevations(vec![250.0, 310.5]);
+        let gpx = route(&graph, &EdgePath { path: vec![Edge::new(0, 1, 5)] }, 0, 1);
+
+        assert!(gpx.contains(r#"<wpt lat="48" lon="9"><ele>250</ele><name>Start</name></wpt>"#));
+        assert!(gpx.contains(r#"<trkpt lat="48.1" lon="9"><ele>310.5</ele></trkpt>"#));
+        assert_eq!(gpx.matches("<trkpt").count(), 2);
+    }
+}
@@ -1,15 +1,28 @@
+use std::cmp::max;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::str::{FromStr, SplitWhitespace};
 use pbr::ProgressBar;
 use crate::Coords;
-use crate::edge::{Edge, EdgeCost};
+use crate::edge::{Edge, EdgeAttributes};
 use crate::node::{Node, NodeIndex};
 
 const GRAPH_FILE_BUFFER_SIZE: usize = 128;
+/// Magic bytes at the start of a binary graph file, the last byte is the format version. Version 1
+/// files hold only nodes and edges, version 2 adds the elevations and version 3 the edge
+/// attributes. All of them can still be read.
+const BINARY_MAGIC: [u8; 8] = *b"RGRAPH\0\x03";
 
 pub struct Graph {
     nodes: Vec<Node>,
     edges: Vec<Edge>,
+    /// The elevation of every node, empty if the graph file doesn't provide them
+    elevations: Vec<f64>,
+    /// The road type and maximum speed of every edge, empty if the graph file doesn't provide them
+    attributes: Vec<EdgeAttributes>,
+    /// The node and edge counts from the header of the graph file, if it was loaded from FMI
+    declared_counts: Option<(usize, usize)>,
 }
 
 const NODE_INDEX_MAX_USIZE: usize = NodeIndex::MAX as usize;
@@ -18,7 +31,10 @@ impl Graph {
     fn new(node_count: usize, edge_count: usize) -> Self {
         Self {
             nodes: Vec::<Node>::with_capacity(node_count),
-            edges: Vec::<Edge>::with_capacity(edge_count)
+            edges: Vec::<Edge>::with_capacity(edge_count),
+            elevations: Vec::new(),
+            attributes: Vec::new(),
+            declared_counts: None,
         }
     }
 
@@ -29,8 +45,31 @@ impl Graph {
     pub fn nodes(&self) -> &Vec<Node> { &self.nodes }
     pub fn node_count(&self) -> usize { self.nodes.len() }
     pub fn edge_count(&self) -> usize { self.edges.len() }
+    pub fn elevations(&self) -> &Vec<f64> { &self.elevations }
+    pub fn declared_counts(&self) -> Option<(usize, usize)> { self.declared_counts }
+    pub fn elevation(&self, index: usize) -> Option<f64> { self.elevations.get(index).copied() }
+    pub fn has_edge_attributes(&self) -> bool { !self.attributes.is_empty() }
+    /// The attributes of an edge, all zero if the graph has none
+    pub fn edge_attributes(&self, index: usize) -> EdgeAttributes { self.attributes.get(index).copied().unwrap_or_default() }
+
+    /// Sets the elevation of every node, `elevations` must either be empty or contain one value per node
+    pub fn set_elevations(&mut self, elevations: Vec<f64>) {
+        assert!(elevations.is_empty() || elevations.len() == self.node_count(), "Expected one elevation per node");
+        self.elevations = elevations;
+    }
+
+    /// Sets the attributes of every edge, `attributes` must either be empty or contain one value per edge
+    pub fn set_edge_attributes(&mut self, attributes: Vec<EdgeAttributes>) {
+        assert!(attributes.is_empty() || attributes.len() == self.edge_count(), "Expected one attribute per edge");
+        self.attributes = attributes;
+    }
 
     pub fn outgoing_edges(&self, index: usize) -> &[Edge] {
+        &self.edges[self.outgoing_edge_range(index)]
+    }
+
+    /// The indices into `edges` of the outgoing edges of a node
+    pub fn outgoing_edge_range(&self, index: usize) -> Range<usize> {
         //Calculate upper the limit of the outgoing edges
         let end = if index == self.node_count() - 1 {
             self.edge_count()
@@ -40,34 +79,82 @@ impl Graph {
 
         let start = self.node(index).unwrap().offset as usize;
         if start == NODE_INDEX_MAX_USIZE {
-            return &[];
+            return 0..0;
+        }
+
+        start..end
+    }
+
+    /// The graph induced by the nodes for which `keep` is true, with the nodes renumbered in their
+    /// original order. Only edges with both ends kept remain.
+    pub fn restrict_to(&self, keep: &[bool]) -> (Graph, NodeMapping) {
+        let mapping = NodeMapping::new(keep);
+        let coords = mapping.original.iter().map(|i| self.nodes[*i].coords).collect();
+        let kept = mapping.original.iter()
+            .flat_map(|i| self.outgoing_edge_range(*i))
+            .filter(|index| mapping.to_new(self.edges[*index].trg as usize).is_some())
+            .collect::<Vec<_>>();
+        let edges = kept.iter().map(|index| {
+            let edge = &self.edges[*index];
+            let (src, trg) = (mapping.to_new(edge.src as usize).unwrap(), mapping.to_new(edge.trg as usize).unwrap());
+            Edge::new(src as NodeIndex, trg as NodeIndex, edge.cost)
+        }).collect();
+        let attributes = if self.has_edge_attributes() { kept.iter().map(|index| self.attributes[*index]).collect() } else { Vec::new() };
+
+        let mut graph = Self::from_edges_with_attributes(coords, edges, attributes);
+        if !self.elevations.is_empty() {
+            graph.elevations = mapping.original.iter().map(|i| self.elevations[*i]).collect();
         }
+        (graph, mapping)
+    }
+
+    /// Builds a graph from node coordinates and edges in any order
+    pub fn from_edges(coords: Vec<Coords>, edges: Vec<Edge>) -> Graph {
+        Self::from_edges_with_attributes(coords, edges, Vec::new())
+    }
 
-        &self.edges[start..end]
+    /// Like `from_edges`, `attributes` is either empty or holds the attributes of `edges` in the same order
+    pub fn from_edges_with_attributes(coords: Vec<Coords>, mut edges: Vec<Edge>, mut attributes: Vec<EdgeAttributes>) -> Graph {
+        assert!(attributes.is_empty() || attributes.len() == edges.len(), "Expected one attribute per edge");
+        if attributes.is_empty() {
+            edges.sort_by_key(|edge| edge.src);
+        } else {
+            let mut order = (0..edges.len()).collect::<Vec<_>>();
+            order.sort_by_key(|index| edges[*index].src);
+            attributes = order.iter().map(|index| attributes[*index]).collect();
+            edges = order.iter().map(|index| edges[*index].clone()).collect();
+        }
+        let mut graph = Self::new(coords.len(), edges.len());
+        let mut offset = 0;
+        for (i, coords) in coords.into_iter().enumerate() {
+            while offset < edges.len() && (edges[offset].src as usize) < i {
+                offset += 1;
+            }
+            graph.nodes.push(Node::new(coords, offset as NodeIndex));
+        }
+        graph.edges = edges;
+        graph.attributes = attributes;
+        graph
     }
 
-    pub fn nearest_node_naive_indices(nodes: &Vec<Node>, indices: &[usize], coords: Coords) -> usize {
+    pub fn nearest_node_naive_indices(nodes: &[Node], indices: &[usize], coords: Coords) -> usize {
         if nodes.is_empty() {
             panic!("The graph loaded is empty!")
         }
 
         let mut lowest_dist: f64 = f64::MAX;
         let mut lowest_index = usize::MAX;
-        indices.iter().map(|i| *i).for_each(|i| {
-            if lowest_index == usize::MAX {
+        indices.iter().copied().for_each(|i| {
+            let dist = coords.distance_to(&nodes[i].coords);
+            if lowest_index == usize::MAX || dist < lowest_dist {
                 lowest_index = i;
-            } else {
-                let dist = coords.distance_to(&nodes[i].coords);
-                if dist < lowest_dist {
-                    lowest_index = i;
-                    lowest_dist = dist;
-                }
+                lowest_dist = dist;
             }
         });
         lowest_index
     }
 
-    pub fn nearest_node_naive(nodes: &Vec<Node>, coords: Coords) -> usize {
+    pub fn nearest_node_naive(nodes: &[Node], coords: Coords) -> usize {
         if nodes.is_empty() {
             panic!("The graph loaded is empty!")
         }
@@ -75,99 +162,488 @@ impl Graph {
         let mut lowest_dist: f64 = f64::MAX;
         let mut lowest_index = usize::MAX;
         for (i, node) in nodes.iter().enumerate() {
-            if lowest_index == usize::MAX {
+            let dist = coords.distance_to(&node.coords);
+            if lowest_index == usize::MAX || dist < lowest_dist {
                 lowest_index = i;
-            } else {
-                let dist = coords.distance_to(&node.coords);
-                if dist < lowest_dist {
-                    lowest_index = i;
-                    lowest_dist = dist;
-                }
+                lowest_dist = dist;
             }
         }
         lowest_index
     }
 
-    pub fn from_file(path: String) -> Graph {
-        let file = File::open(path).expect("Couldn't open the graph file. Please check if the path is valid!");
-        let mut reader = BufReader::new(file);
-        let mut node_count: usize = 0;
-        let mut edge_count: usize = 0;
-        let mut line_buf = String::with_capacity(GRAPH_FILE_BUFFER_SIZE);
-        let mut is_node_count = true;
-        while reader.read_line(&mut line_buf).unwrap() != 0 {
-            let buf: &str = &line_buf[0..line_buf.len()-1];
-            if !buf.is_empty() && buf.as_bytes().first() != Some(&b'#') {
-                if is_node_count {
-                    node_count = buf.parse::<usize>().unwrap();
-                    is_node_count = false;
-                } else {
-                    edge_count = buf.parse::<usize>().unwrap();
-                    break;
-                }
-            } else {
-            }
-            line_buf.clear();
+    /// Loads a graph either from a binary graph file or from a FMI text file
+    pub fn from_file(path: String) -> Result<Graph, String> {
+        let mut builder = GraphBuilder::default();
+        let declared_counts = read_file(path, &mut builder)?;
+        Ok(builder.finish(declared_counts))
+    }
+
+    /// Writes the graph in the FMI text format. Attributes which are not kept in memory are written as zero.
+    pub fn write_fmi_file(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# Written by router")?;
+        writeln!(writer)?;
+        writeln!(writer, "{}", self.node_count())?;
+        writeln!(writer, "{}", self.edge_count())?;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let elevation = self.elevation(i).unwrap_or(0.0);
+            writeln!(writer, "{} {} {} {} {}", i, i, node.coords.lat, node.coords.lon, elevation)?;
         }
+        for (i, edge) in self.edges.iter().enumerate() {
+            let attributes = self.edge_attributes(i);
+            writeln!(writer, "{} {} {} {} {}", edge.src, edge.trg, edge.cost, attributes.road_type, attributes.max_speed)?;
+        }
+        writer.flush()
+    }
 
-        if edge_count < 1 || node_count < 1 {
-            panic!("Invalid graph file.")
+    /// Writes the graph in router's own little-endian binary format, which loads much faster than FMI
+    pub fn write_binary_file(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_binary(&mut writer)?;
+        writer.flush()
+    }
+
+    fn write_binary(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(&BINARY_MAGIC)?;
+        writer.write_all(&(self.node_count() as u64).to_le_bytes())?;
+        writer.write_all(&(self.edge_count() as u64).to_le_bytes())?;
+        for node in &self.nodes {
+            writer.write_all(&node.coords.lat.to_le_bytes())?;
+            writer.write_all(&node.coords.lon.to_le_bytes())?;
+            writer.write_all(&node.offset.to_le_bytes())?;
+        }
+        writer.write_all(&(self.elevations.len() as u64).to_le_bytes())?;
+        for elevation in &self.elevations {
+            writer.write_all(&elevation.to_le_bytes())?;
+        }
+        for edge in &self.edges {
+            writer.write_all(&edge.src.to_le_bytes())?;
+            writer.write_all(&edge.trg.to_le_bytes())?;
+            writer.write_all(&edge.cost.to_le_bytes())?;
+        }
+        writer.write_all(&(self.attributes.len() as u64).to_le_bytes())?;
+        for attributes in &self.attributes {
+            writer.write_all(&[attributes.road_type])?;
+            writer.write_all(&attributes.max_speed.to_le_bytes())?;
         }
+        Ok(())
+    }
+}
+
+/// The edge indices of a graph grouped by their target, so searches can follow the edges backwards
+/// without building a reversed graph. Edges with an invalid target are left out.
+pub struct IncomingEdges {
+    /// One more entry than nodes, the incoming edges of node `i` are `edges[offsets[i]..offsets[i + 1]]`
+    offsets: Vec<usize>,
+    edges: Vec<usize>,
+}
 
-        let mut graph = Self::new(node_count, edge_count);
-        let mut i: usize = 0;
-        let border = ((edge_count + node_count) / 100) as usize;
-        let mut pb = ProgressBar::new(100);
-        pb.show_speed = false;
-        let mut last_edge_src = 0;
-        let mut last_edge_cnt: NodeIndex = 0;
+impl IncomingEdges {
+    pub fn new(graph: &Graph) -> Self {
+        let node_count = graph.node_count();
+        let is_valid = |trg: NodeIndex| trg >= 0 && (trg as usize) < node_count;
+        let mut offsets = vec![0; node_count + 1];
+        for edge in graph.edges.iter().filter(|edge| is_valid(edge.trg)) {
+            offsets[edge.trg as usize + 1] += 1;
+        }
+        for i in 0..node_count {
+            offsets[i + 1] += offsets[i];
+        }
+
+        // A counting sort, so the edges of every node keep their order
+        let mut next = offsets.clone();
+        let mut edges = vec![0; offsets[node_count]];
+        for (index, edge) in graph.edges.iter().enumerate().filter(|(_, edge)| is_valid(edge.trg)) {
+            edges[next[edge.trg as usize]] = index;
+            next[edge.trg as usize] += 1;
+        }
+        Self { offsets, edges }
+    }
+
+    /// The positions of the incoming edges of `node`, see `edge_index`
+    pub fn range(&self, node: usize) -> Range<usize> {
+        self.offsets[node]..self.offsets[node + 1]
+    }
+
+    /// The index in the graph of the incoming edge at `position`
+    pub fn edge_index(&self, position: usize) -> usize {
+        self.edges[position]
+    }
+}
+
+/// Receives the nodes and then the edges of a graph file in the order of the file, so it can be
+/// loaded into other representations than `Graph` without building one first
+pub trait GraphSink {
+    /// Called before the first node, the counts are capped to what the file can hold
+    fn reserve(&mut self, node_count: usize, edge_count: usize);
+    fn node(&mut self, coords: Coords);
+    /// The elevation of the next node, only called if the file has elevations
+    fn elevation(&mut self, elevation: f64);
+    fn edge(&mut self, edge: Edge) -> Result<(), String>;
+    /// The attributes of the next edge, only called if the file has attributes
+    fn edge_attributes(&mut self, attributes: EdgeAttributes);
+}
+
+/// Reads a binary graph file or a FMI text file into `sink`. Returns the counts declared in the
+/// header of FMI files.
+pub fn read_file(path: String, sink: &mut impl GraphSink) -> Result<Option<(usize, usize)>, String> {
+    let open = || File::open(&path).map_err(|err| format!("Couldn't open the graph file: {}", err));
+    let mut file = open()?;
+    let size = file.metadata().map_err(|err| format!("Couldn't open the graph file: {}", err))?.len();
+    let mut magic = [0u8; BINARY_MAGIC.len()];
+    if file.read_exact(&mut magic).is_ok() && magic[..7] == BINARY_MAGIC[..7] {
+        read_binary(&mut BufReader::new(open()?), size, sink).map_err(|err| format!("Couldn't read the binary graph file: {}", err))?;
+        return Ok(None);
+    }
+
+    read_fmi(BufReader::new(open()?), size, sink).map(Some)
+}
+
+/// Reads a FMI graph from a reader which holds `size` bytes. Errors name the line which couldn't be
+/// read.
+fn read_fmi(mut reader: impl BufRead, size: u64, sink: &mut impl GraphSink) -> Result<(usize, usize), String> {
+    let mut line_number = 0;
+    let mut line_buf = String::with_capacity(GRAPH_FILE_BUFFER_SIZE);
+    let mut read_line = |line_buf: &mut String, line_number: &mut usize| {
         line_buf.clear();
-        while reader.read_line(&mut line_buf).unwrap() != 0 {
-            i += 1;
-            if i % border == 0 {
-                pb.inc();
+        *line_number += 1;
+        reader.read_line(line_buf).map_err(|err| format!("Line {}: {}", line_number, err))
+    };
+
+    // The node count and then the edge count, after comments and empty lines
+    let mut counts = Vec::new();
+    while counts.len() < 2 {
+        if read_line(&mut line_buf, &mut line_number)? == 0 {
+            return Err("The graph file ends before the node and edge counts.".to_string());
+        }
+        let buf = line_buf.trim_end();
+        if !buf.is_empty() && !buf.starts_with('#') {
+            let count = buf.parse::<usize>().map_err(|_| format!("Line {}: expected a count but got '{}'.", line_number, buf))?;
+            counts.push(count);
+        }
+    }
+    let (node_count, edge_count) = (counts[0], counts[1]);
+    if edge_count < 1 || node_count < 1 {
+        return Err("The graph file declares no nodes or no edges.".to_string());
+    }
+
+    // Every node line takes at least 8 bytes and every edge line 6, larger counts must not be allocated
+    let size = size as usize;
+    sink.reserve(node_count.min(size / 8), edge_count.min(size / 6));
+    let (mut nodes, mut edges) = (0, 0);
+    let mut i: usize = 0;
+    let border = max(1, (edge_count + node_count) / 100);
+    let mut pb = ProgressBar::new(100);
+    pb.show_speed = false;
+    while read_line(&mut line_buf, &mut line_number)? != 0 {
+        i += 1;
+        if i % border == 0 {
+            pb.inc();
+        }
+
+        let buf = line_buf.trim_end();
+        if buf.is_empty() {
+            continue;
+        }
+        let mut fields = buf.split_whitespace();
+        if nodes < node_count {
+            // The node id and the OSM id are not needed
+            fields.nth(1);
+            let mut coords = Coords::default();
+            coords.set_lat_deg(parse_field(&mut fields, "latitude", line_number)?);
+            coords.set_lon_deg(parse_field(&mut fields, "longitude", line_number)?);
+            sink.node(coords);
+            nodes += 1;
+            if let Some(elevation) = fields.next().and_then(|elevation| elevation.parse::<f64>().ok()) {
+                sink.elevation(elevation);
             }
+        } else {
+            let src = parse_field(&mut fields, "source", line_number)?;
+            let trg = parse_field(&mut fields, "target", line_number)?;
+            let cost = parse_field(&mut fields, "cost", line_number)?;
+            sink.edge(Edge::new(src, trg, cost)).map_err(|err| format!("Line {}: {}", line_number, err))?;
+            edges += 1;
+            let road_type = fields.next().and_then(|road_type| road_type.parse::<u8>().ok());
+            let max_speed = fields.next().and_then(|max_speed| max_speed.parse::<u16>().ok());
+            if let (Some(road_type), Some(max_speed)) = (road_type, max_speed) {
+                sink.edge_attributes(EdgeAttributes { road_type, max_speed });
+            }
+        }
+    }
 
-            //Remove the newline
-            let buf: &str = &line_buf[0..line_buf.len() - 1];
-            if graph.nodes.len() < node_count {
-                let mut node = Node::default();
-                let mut it = buf.split(char::is_whitespace);
-                it.next();
-                //node.offset = it.next().unwrap().parse::<NodeIndex>().unwrap();
-                it.next();
-
-
-                let mut coords = Coords::default();
-                coords.set_lat_deg(it.next().unwrap().parse::<f64>().unwrap());
-                coords.set_lon_deg(it.next().unwrap().parse::<f64>().unwrap());
-                node.coords = coords;
-                graph.nodes.push(node);
-            } else {
-                let mut edge = Edge::default();
-                let mut it = buf.split(char::is_whitespace);
-                edge.src = it.next().unwrap().parse::<NodeIndex>().unwrap();
-                if last_edge_src != edge.src {
-                    graph.nodes[last_edge_src as usize].offset = last_edge_cnt;
-                    last_edge_cnt = graph.edges.len() as NodeIndex;
-
-                    //Set offsets for nodes which have no outgoing edges
-                    (last_edge_src+1..edge.src).map(|i| i as usize).for_each(|i| graph.nodes[i].offset = last_edge_cnt);
-
-                    last_edge_src = edge.src;
-                }
-
-                edge.trg = it.next().unwrap().parse::<NodeIndex>().unwrap();
-                edge.cost = it.next().unwrap().parse::<EdgeCost>().unwrap();
-                graph.edges.push(edge);
+    println!("\nProcessed {} lines, {} / {} edges, {} / {} nodes", i, edges, edge_count, nodes, node_count);
+    Ok((node_count, edge_count))
+}
+
+/// Reads a binary graph of any version from a reader which holds `size` bytes. The stored offsets
+/// are skipped, sinks derive them from the edges.
+fn read_binary(reader: &mut impl Read, size: u64, sink: &mut impl GraphSink) -> io::Result<()> {
+    let mut magic = [0u8; BINARY_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic[..7] != BINARY_MAGIC[..7] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a binary graph file."));
+    }
+    let version = magic[7];
+    if version == 0 || version > BINARY_MAGIC[7] {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unsupported binary graph version {}.", version)));
+    }
+
+    let node_count = read_u64(reader)? as usize;
+    let edge_count = read_u64(reader)? as usize;
+    // Every node and edge takes 24 bytes, larger counts come from a broken file and must not be
+    // allocated
+    let data_size = node_count.checked_add(edge_count).and_then(|count| count.checked_mul(24));
+    if data_size.map_or(true, |data_size| data_size as u64 > size) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "The node and edge counts don't fit the size of the file."));
+    }
+    sink.reserve(node_count, edge_count);
+    for _ in 0..node_count {
+        let lat = read_f64(reader)?;
+        let lon = read_f64(reader)?;
+        read_i64(reader)?;
+        sink.node(Coords::deg(lat, lon));
+    }
+    if version >= 2 {
+        let elevation_count = read_u64(reader)? as usize;
+        for _ in 0..elevation_count {
+            sink.elevation(read_f64(reader)?);
+        }
+    }
+    for _ in 0..edge_count {
+        let src = read_i64(reader)?;
+        let trg = read_i64(reader)?;
+        let cost = read_i64(reader)?;
+        sink.edge(Edge::new(src, trg, cost)).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    }
+    if version >= 3 {
+        let attribute_count = read_u64(reader)? as usize;
+        for _ in 0..attribute_count {
+            let mut buf = [0u8; 3];
+            reader.read_exact(&mut buf)?;
+            sink.edge_attributes(EdgeAttributes { road_type: buf[0], max_speed: u16::from_le_bytes([buf[1], buf[2]]) });
+        }
+    }
+    if reader.read(&mut [0u8])? != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Unexpected data after a version {} binary graph.", version)));
+    }
+    Ok(())
+}
+
+/// Builds a `Graph`. The offsets are derived from the order of the edges, which have to be sorted by
+/// their source. Other orders are left to the validator.
+struct GraphBuilder {
+    graph: Graph,
+    last_edge_src: NodeIndex,
+    last_edge_cnt: NodeIndex,
+}
+
+impl Default for GraphBuilder {
+    fn default() -> Self {
+        Self { graph: Graph::new(0, 0), last_edge_src: 0, last_edge_cnt: 0 }
+    }
+}
+
+impl GraphSink for GraphBuilder {
+    fn reserve(&mut self, node_count: usize, edge_count: usize) {
+        self.graph.nodes.reserve(node_count);
+        self.graph.edges.reserve(edge_count);
+    }
+
+    fn node(&mut self, coords: Coords) {
+        self.graph.nodes.push(Node { coords, ..Node::default() });
+    }
+
+    fn elevation(&mut self, elevation: f64) {
+        self.graph.elevations.push(elevation);
+    }
+
+    fn edge(&mut self, edge: Edge) -> Result<(), String> {
+        let graph = &mut self.graph;
+        if self.last_edge_src != edge.src {
+            //Invalid sources are left to the validator
+            if let Some(node) = graph.nodes.get_mut(self.last_edge_src as usize) {
+                node.offset = self.last_edge_cnt;
             }
-            line_buf.clear();
+            self.last_edge_cnt = graph.edges.len() as NodeIndex;
+
+            //Set offsets for nodes which have no outgoing edges
+            graph.nodes.iter_mut().take(edge.src.max(0) as usize).skip((self.last_edge_src + 1).max(0) as usize)
+                .for_each(|node| node.offset = self.last_edge_cnt);
+
+            self.last_edge_src = edge.src;
         }
+        graph.edges.push(edge);
+        Ok(())
+    }
+
+    fn edge_attributes(&mut self, attributes: EdgeAttributes) {
+        self.graph.attributes.push(attributes);
+    }
+}
 
-        graph.nodes[last_edge_src as usize].offset = last_edge_cnt;
-        #[cfg(debug_assertions)]
-        graph.nodes.iter().for_each(|node| assert_ne!(node.offset, NodeIndex::MAX));
-        println!("\nProcessed {} lines, {} / {} edges, {} / {} nodes", i, graph.edges.len(), edge_count, graph.nodes.len(), node_count);
+impl GraphBuilder {
+    fn finish(self, declared_counts: Option<(usize, usize)>) -> Graph {
+        let mut graph = self.graph;
+        if let Some(node) = graph.nodes.get_mut(self.last_edge_src as usize) {
+            node.offset = self.last_edge_cnt;
+        }
+        //Nodes after the last source have no outgoing edges either
+        let edge_len = graph.edges.len() as NodeIndex;
+        graph.nodes.iter_mut().skip((self.last_edge_src + 1).max(0) as usize).for_each(|node| node.offset = edge_len);
+        if graph.elevations.len() != graph.nodes.len() {
+            graph.elevations.clear();
+        }
+        if graph.attributes.len() != graph.edges.len() {
+            graph.attributes.clear();
+        }
+        graph.declared_counts = declared_counts;
         graph
     }
-}
\ No newline at end of file
+}
+
+/// Translates between the node ids of a graph and of a graph derived from it by dropping nodes
+pub struct NodeMapping {
+    /// The original id of every new node
+    original: Vec<usize>,
+    /// The new id of every original node, `usize::MAX` if it was dropped
+    new: Vec<usize>,
+}
+
+impl NodeMapping {
+    fn new(keep: &[bool]) -> Self {
+        let mut original = Vec::new();
+        let new = keep.iter().enumerate().map(|(i, keep)| {
+            if *keep {
+                original.push(i);
+                original.len() - 1
+            } else {
+                usize::MAX
+            }
+        }).collect();
+        Self { original, new }
+    }
+
+    pub fn to_original(&self, new: usize) -> usize { self.original[new] }
+    pub fn to_new(&self, original: usize) -> Option<usize> {
+        self.new.get(original).copied().filter(|new| *new != usize::MAX)
+    }
+
+    /// Writes one `<new id> <original id>` line per kept node
+    pub fn write_file(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for (new, original) in self.original.iter().enumerate() {
+            writeln!(writer, "{} {}", new, original)?;
+        }
+        writer.flush()
+    }
+}
+
+/// Parses the next field of a line, `name` describes it in the error
+fn parse_field<T: FromStr>(fields: &mut SplitWhitespace, name: &str, line_number: usize) -> Result<T, String> {
+    let field = fields.next().ok_or_else(|| format!("Line {}: the {} is missing.", line_number, name))?;
+    field.parse::<T>().map_err(|_| format!("Line {}: expected the {} but got '{}'.", line_number, name, field))
+}
+
+pub(crate) fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub(crate) fn read_i64(reader: &mut impl Read) -> io::Result<i64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(i64::from_le_bytes(buf))
+}
+
+fn read_f64(reader: &mut impl Read) -> io::Result<f64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_binary, read_fmi, Graph, GraphBuilder, BINARY_MAGIC};
+    use crate::coords::Coords;
+    use crate::edge::{Edge, EdgeAttributes};
+
+    fn graph() -> Graph {
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(48.1, 9.1), Coords::deg(48.2, 9.2)];
+        Graph::from_edges(coords, vec![Edge::new(0, 1, 5), Edge::new(1, 2, 7), Edge::new(2, 0, 3)])
+    }
+
+    fn edges(graph: &Graph) -> Vec<(i64, i64, i64)> {
+        graph.edges().iter().map(|edge| (edge.src, edge.trg, edge.cost)).collect()
+    }
+
+    fn read(bytes: &[u8]) -> std::io::Result<Graph> {
+        let mut builder = GraphBuilder::default();
+        read_binary(&mut &bytes[..], bytes.len() as u64, &mut builder)?;
+        Ok(builder.finish(None))
+    }
+
+    #[test]
+    fn binary_versions() {
+        let mut graph = graph();
+        graph.set_elevations(vec![100.0, 200.0, 300.0]);
+        graph.set_edge_attributes(vec![EdgeAttributes { road_type: 3, max_speed: 50 }; 3]);
+        let mut bytes = Vec::new();
+        graph.write_binary(&mut bytes).unwrap();
+        let read_back = read(&bytes).unwrap();
+        assert_eq!(edges(&read_back), edges(&graph));
+        assert_eq!(read_back.elevations(), graph.elevations());
+        assert_eq!(read_back.edge_attributes(1), EdgeAttributes { road_type: 3, max_speed: 50 });
+
+        // Version 1 has neither elevations nor attributes, version 2 no attributes
+        let node_bytes = 16 + 24 * 3;
+        let edge_bytes = 24 * 3;
+        let elevation_bytes = 8 + 8 * 3;
+        let mut v1 = bytes[..8 + node_bytes].to_vec();
+        v1[7] = 1;
+        v1.extend(&bytes[8 + node_bytes + elevation_bytes..][..edge_bytes]);
+        let v1 = read(&v1).unwrap();
+        assert_eq!(edges(&v1), edges(&graph));
+        assert!(v1.elevations().is_empty() && !v1.has_edge_attributes());
+
+        let mut v2 = bytes[..8 + node_bytes + elevation_bytes + edge_bytes].to_vec();
+        v2[7] = 2;
+        let v2 = read(&v2).unwrap();
+        assert_eq!(v2.elevations(), graph.elevations());
+        assert!(!v2.has_edge_attributes());
+
+        // A layout that doesn't match the version is rejected instead of misread
+        let mut wrong = bytes.clone();
+        wrong[7] = 1;
+        assert!(read(&wrong).is_err());
+        wrong[7] = BINARY_MAGIC[7] + 1;
+        assert!(read(&wrong).is_err());
+        assert!(read(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn fmi() {
+        let read = |text: &str| {
+            let mut builder = GraphBuilder::default();
+            read_fmi(text.as_bytes(), text.len() as u64, &mut builder).map(|counts| builder.finish(Some(counts)))
+        };
+        let graph = read("# comment\n\n3\n2\n0 0 48.0 9.0 100\n1 1 48.1 9.1 200\n2 2 48.2 9.2 300\n0 1 5 3 50\n2 0 7 3 50").unwrap();
+        assert_eq!(edges(&graph), vec![(0, 1, 5), (2, 0, 7)]);
+        assert_eq!(graph.outgoing_edges(1).len(), 0);
+        assert_eq!(graph.elevations(), &vec![100.0, 200.0, 300.0]);
+        assert_eq!(graph.edge_attributes(1), EdgeAttributes { road_type: 3, max_speed: 50 });
+
+        assert_eq!(read("2\nmany\n").err().unwrap(), "Line 2: expected a count but got 'many'.");
+        assert_eq!(read("2\n1\n0 0 48.0 9.0\n1 1 48.1\n0 1 5").err().unwrap(), "Line 4: the longitude is missing.");
+        assert_eq!(read("2\n1\n0 0 48.0 9.0\n1 1 48.1 9.1\n0 1 x").err().unwrap(), "Line 5: expected the cost but got 'x'.");
+        assert!(read("2\n").is_err());
+    }
+
+    #[test]
+    fn binary_counts() {
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend(u64::MAX.to_le_bytes());
+        bytes.extend(1u64.to_le_bytes());
+        assert!(read(&bytes).is_err());
+    }
+}
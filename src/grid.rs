@@ -1,10 +1,88 @@
 use core::panic;
 use std::cmp::max;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 use owo_colors::OwoColorize;
 use pbr::ProgressBar;
 
-use crate::{coords::Coords, graph::Graph, node::Node};
+use crate::{components, coords::{BoundingBox, Coords}, graph::Graph, node::Node};
+
+/// Which nodes a query location may be snapped to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SnapFilter {
+	All,
+	/// Nodes with at least one outgoing edge
+	Outgoing,
+	/// Nodes of the largest strongly connected component
+	LargestComponent,
+}
+
+impl SnapFilter {
+	/// Marks the nodes which pass the filter
+	pub fn mask(&self, graph: &Graph) -> Vec<bool> {
+		match self {
+			SnapFilter::All => vec![true; graph.node_count()],
+			SnapFilter::Outgoing => (0..graph.node_count())
+				.map(|i| !graph.outgoing_edges(i).is_empty())
+				.collect(),
+			SnapFilter::LargestComponent => {
+				let components = components::strongly_connected(graph);
+				match components.largest() {
+					Some(largest) => components.members(largest),
+					None => Vec::new(),
+				}
+			}
+		}
+	}
+}
+
+impl FromStr for SnapFilter {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"all" => Ok(SnapFilter::All),
+			"outgoing" => Ok(SnapFilter::Outgoing),
+			"largest-scc" => Ok(SnapFilter::LargestComponent),
+			_ => Err(format!("Unknown filter '{}', expected 'all', 'outgoing' or 'largest-scc'", s)),
+		}
+	}
+}
+
+/// The tree of the nodes passing a snap filter, only built once the first coordinate is snapped.
+/// Clones share the tree.
+#[derive(Clone)]
+pub struct SnapTree {
+	filter: SnapFilter,
+	tree: Arc<Mutex<Option<Arc<NodeTree>>>>,
+}
+
+impl SnapTree {
+	pub fn new(filter: SnapFilter) -> Self {
+		Self { filter, tree: Arc::new(Mutex::new(None)) }
+	}
+
+	/// Uses a tree which was already built, it must index exactly the nodes passing the filter
+	pub fn with_tree(filter: SnapFilter, tree: NodeTree) -> Self {
+		Self { filter, tree: Arc::new(Mutex::new(Some(Arc::new(tree)))) }
+	}
+
+	/// The tree, built on the first call. Other threads wait for it instead of building it again.
+	pub fn get(&self, graph: &Graph) -> Arc<NodeTree> {
+		let mut tree = self.tree.lock().unwrap();
+		tree.get_or_insert_with(|| {
+			let mask = self.filter.mask(graph);
+			Arc::new(NodeTree::build_filtered(graph.nodes(), |i| mask[i]))
+		}).clone()
+	}
+}
+
+impl Default for SnapTree {
+	fn default() -> Self {
+		Self::new(SnapFilter::All)
+	}
+}
 
 #[derive(Clone)]
 pub enum NodeTree {
@@ -106,6 +184,25 @@ impl NodeTree {
 		}
 	}
 
+	/// The center and side length of every leaf, i.e. the quadrants the tree divides the world into
+	pub fn quadrants(&self) -> Vec<(Coords, f64)> {
+		let mut quadrants = Vec::new();
+		let mut stack = vec![self];
+
+		while let Some(tree) = stack.pop() {
+			match tree {
+				Self::Node { center: _, next } => stack.extend(next.iter()),
+				Self::Leaf {
+					center,
+					size,
+					nodes: _,
+				} => quadrants.push((*center, *size)),
+			}
+		}
+
+		quadrants
+	}
+
 	pub fn relative_position(coord1: Coords, coord2: Coords) -> usize {
 		let lat = coord1.lat < coord2.lat;
 		let lon = coord1.lon > coord2.lon;
@@ -114,7 +211,7 @@ impl NodeTree {
 	}
 
 	#[must_use]
-	pub fn nearest_node(&self, graph_nodes: &Vec<Node>, coords: Coords) -> usize {
+	pub fn nearest_node(&self, graph_nodes: &[Node], coords: Coords) -> usize {
 		let mut prev_next: Option<&Box<[Self; 4]>> = None;
 		let mut node = self;
 		let mut last_pos = usize::MAX;
@@ -173,7 +270,113 @@ impl NodeTree {
 		}
 	}
 
-	pub fn build(graph_nodes: &Vec<Node>) -> Self {
+	/// Whether no node was indexed
+	pub fn is_empty(&self) -> bool {
+		matches!(self, Self::Leaf { nodes: None, .. })
+	}
+
+	/// The `count` indexed nodes nearest to `coords`, the nearest first. The smallest subtree around
+	/// `coords` with enough nodes bounds the distance, then all nodes within that distance are taken
+	/// from the quadrants around it.
+	pub fn nearest_nodes(&self, graph_nodes: &[Node], coords: Coords, count: usize) -> Vec<usize> {
+		let mut path = vec![self];
+		let mut tree = self;
+		while let Self::Node { center, next } = tree {
+			tree = &next[Self::relative_position(coords, *center)];
+			path.push(tree);
+		}
+
+		let mut candidates = Vec::new();
+		for subtree in path.iter().rev() {
+			candidates.clear();
+			subtree.collect_indices(&mut candidates);
+			if candidates.len() >= count {
+				break;
+			}
+		}
+
+		let distance = |index: &usize| coords.distance_to(&graph_nodes[*index].coords);
+		candidates.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+		if count > 0 && candidates.len() >= count {
+			// Nodes in the neighbouring quadrants can be closer than the farthest candidate. Distances
+			// of close points are off by up to about 0.1 m through rounding, the slack covers that.
+			let radius = distance(&candidates[count - 1]) + 1.0;
+			candidates = self.nodes_in_bbox(graph_nodes, &BoundingBox::around_circle(coords, radius));
+			candidates.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+		}
+		candidates.truncate(count);
+		candidates
+	}
+
+	/// The indexed nodes inside the bounding box. Only the quadrants which can overlap it are visited.
+	pub fn nodes_in_bbox(&self, graph_nodes: &[Node], bbox: &BoundingBox) -> Vec<usize> {
+		let mut found = Vec::new();
+		let mut stack = vec![self];
+
+		while let Some(tree) = stack.pop() {
+			match tree {
+				// The quadrants are bounded by the centers only, nodes outside of the root square are
+				// sorted into its outer quadrants as well
+				Self::Node { center, next } => {
+					let north = bbox.max.lat >= center.lat;
+					let south = bbox.min.lat < center.lat;
+					let west = bbox.min.lon <= center.lon;
+					let east = bbox.max.lon > center.lon;
+					let overlaps = [north && west, north && east, south && west, south && east];
+					stack.extend(next.iter().zip(overlaps).filter(|(_, overlaps)| *overlaps).map(|(next, _)| next));
+				}
+				Self::Leaf {
+					center: _,
+					size: _,
+					nodes,
+				} => found.extend(nodes.iter().flatten().filter(|index| bbox.contains(&graph_nodes[**index].coords))),
+			}
+		}
+
+		found
+	}
+
+	/// The bytes allocated by the tree, including the node indices
+	pub fn memory_size(&self) -> usize {
+		let mut bytes = std::mem::size_of::<Self>();
+		let mut stack = vec![self];
+		while let Some(tree) = stack.pop() {
+			match tree {
+				Self::Node { center: _, next } => {
+					bytes += std::mem::size_of::<[Self; 4]>();
+					stack.extend(next.iter());
+				}
+				Self::Leaf {
+					center: _,
+					size: _,
+					nodes,
+				} => bytes += nodes.as_ref().map_or(0, |nodes| nodes.capacity() * std::mem::size_of::<usize>()),
+			}
+		}
+		bytes
+	}
+
+	fn collect_indices(&self, out: &mut Vec<usize>) {
+		let mut stack = vec![self];
+		while let Some(tree) = stack.pop() {
+			match tree {
+				Self::Node { center: _, next } => stack.extend(next.iter()),
+				Self::Leaf {
+					center: _,
+					size: _,
+					nodes,
+				} => out.extend(nodes.iter().flatten()),
+			}
+		}
+	}
+
+	pub fn build(graph_nodes: &[Node]) -> Self {
+		Self::build_filtered(graph_nodes, |_| true)
+	}
+
+	/// Like `build`, but only indexes the nodes for which `keep` returns true. Nearest node queries
+	/// will then only return these nodes.
+	pub fn build_filtered(graph_nodes: &[Node], keep: impl Fn(usize) -> bool) -> Self {
 		let mut element_count = 0;
 		let mut tree = Self::root_leaf();
 
@@ -185,7 +388,7 @@ impl NodeTree {
 
 		let mut subdivisions = 0;
 
-		let border = (graph_nodes.len() / 100) as usize;
+		let border = max(1, graph_nodes.len() / 100);
 		let mut pb = ProgressBar::new(100);
 		pb.show_speed = false;
 
@@ -194,6 +397,10 @@ impl NodeTree {
 				pb.inc();
 			}
 
+			if !keep(i) {
+				continue;
+			}
+
 			let mut iy = 0;
 
 			loop {
@@ -251,7 +458,76 @@ impl NodeTree {
 #[cfg(test)]
 mod tests {
 	use super::NodeTree;
-	use crate::{coords::Coords, node::Node};
+	use crate::{coords::{BoundingBox, Coords}, node::Node, rng::Rng};
+
+	#[test]
+	fn filtered() {
+		let nodes = [
+			Node::new(Coords::deg(48.0, 9.0), 0),
+			Node::new(Coords::deg(48.001, 9.001), 0),
+			Node::new(Coords::deg(48.5, 9.5), 0),
+		];
+		let tree = NodeTree::build_filtered(&nodes, |i| i != 0);
+
+		assert_eq!(tree.nearest_node(&nodes, Coords::deg(48.0, 9.0)), 1);
+		assert_eq!(tree.nearest_nodes(&nodes, Coords::deg(48.0, 9.0), 5), vec![1, 2]);
+	}
+
+	#[test]
+	fn exact_nearest() {
+		let nodes = (0..4).map(|i| Node::new(Coords::deg(48.0, 9.0 + i as f64 / 10.0), 0)).collect::<Vec<_>>();
+		let tree = NodeTree::build_filtered(&nodes, |i| i != 2);
+
+		// Picking the sibling quadrant by its centre misses node 1, which is closer than node 3
+		let coords = Coords::deg(48.0, 9.18);
+		assert_eq!(tree.nearest_node(&nodes, coords), 3);
+		assert_eq!(tree.nearest_nodes(&nodes, coords, 1), vec![1]);
+	}
+
+	#[test]
+	fn close_nodes() {
+		// Meters apart, where rounding shrinks the distances slightly
+		let nodes = (0..4).map(|i| Node::new(Coords::deg(48.0, 9.0 + i as f64 / 1000.0), 0)).collect::<Vec<_>>();
+		let tree = NodeTree::build(&nodes);
+		assert_eq!(tree.nearest_nodes(&nodes, Coords::deg(48.0, 9.0019), 1), vec![2]);
+		assert_eq!(tree.nearest_nodes(&nodes, Coords::deg(48.0, 9.0), 2), vec![0, 1]);
+	}
+
+	#[test]
+	fn bbox() {
+		let nodes = [
+			Node::new(Coords::deg(48.0, 9.0), 0),
+			Node::new(Coords::deg(48.001, 9.001), 0),
+			Node::new(Coords::deg(48.5, 9.5), 0),
+			Node::new(Coords::deg(-33.9, 151.2), 0),
+		];
+		let tree = NodeTree::build(&nodes);
+
+		let mut found = tree.nodes_in_bbox(&nodes, &BoundingBox::new(Coords::deg(47.9, 8.9), Coords::deg(48.2, 9.6)));
+		found.sort();
+		assert_eq!(found, vec![0, 1]);
+		assert_eq!(tree.nodes_in_bbox(&nodes, &BoundingBox::new(Coords::deg(-34.0, 151.0), Coords::deg(-33.0, 152.0))), vec![3]);
+	}
+
+	#[test]
+	fn nearest() {
+		let mut rng = Rng::new(3);
+		let mut coord = |min: f64, max: f64| min + (max - min) * rng.below(1_000_000) as f64 / 1_000_000.0;
+		let nodes = (0..2000).map(|_| Node::new(Coords::deg(coord(47.0, 49.0), coord(8.0, 10.0)), 0)).collect::<Vec<_>>();
+		let tree = NodeTree::build(&nodes);
+
+		for _ in 0..50 {
+			let coords = Coords::deg(coord(46.5, 49.5), coord(7.5, 10.5));
+			let distance = |index: &usize| coords.distance_to(&nodes[*index].coords);
+			let mut expected = (0..nodes.len()).collect::<Vec<_>>();
+			expected.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+			for count in [1, 5, 30] {
+				let found = tree.nearest_nodes(&nodes, coords, count);
+				assert_eq!(found.iter().map(distance).collect::<Vec<_>>(), expected[..count].iter().map(distance).collect::<Vec<_>>());
+			}
+		}
+		assert!(NodeTree::build_filtered(&nodes, |_| false).is_empty());
+	}
 
 	#[test]
 	fn position() {
@@ -1,23 +1,59 @@
 pub mod args;
+pub mod avoid;
+pub mod bench;
+pub mod compact;
+pub mod components;
 pub mod coords;
+pub mod cost;
+pub mod delta;
+pub mod distances;
 pub mod edge;
+pub mod generate;
+pub mod geojson;
+pub mod gpx;
 pub mod graph;
 pub mod grid;
 pub mod node;
+pub mod overlay;
+pub mod polyline;
+pub mod profile;
+pub mod queue;
+pub mod rng;
 pub mod router;
+pub mod server;
+pub mod stats;
+pub mod svg;
+pub mod tsp;
+pub mod validate;
 
 use owo_colors::OwoColorize;
 use std::cmp::min;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::mem::size_of;
 use std::process::exit;
 use std::sync::Arc;
 use std::time::Instant;
 use std::{env, io};
 
-use crate::coords::Coords;
-use crate::graph::Graph;
-use crate::grid::NodeTree;
-use crate::node::NodeIndex;
-use args::Args;
+use crate::avoid::{Area, Avoid};
+use crate::coords::{BoundingBox, Coords};
+use crate::cost::Metric;
+use crate::distances::DistanceTable;
+use crate::graph::{Graph, IncomingEdges, NodeMapping};
+use crate::overlay::{CostOverlay, Overlaid};
+use crate::grid::{NodeTree, SnapFilter, SnapTree};
+use crate::edge::{Edge, EdgeCost};
+use crate::node::{Node, NodeIndex};
+use crate::bench::{BenchGraph, BenchQuery};
+use crate::compact::CompactGraph;
+use crate::stats::GraphStats;
+use crate::profile::Profiles;
+use crate::queue::QueueKind;
+use crate::router::{Algorithm, Direction, Location, SearchTrace, SolveOptions};
+use crate::svg::{Projection, SvgRenderer};
+use crate::tsp::TourKind;
+use args::{Args, Command};
 
 fn main() {
 	println!(
@@ -28,107 +64,926 @@ fn main() {
 	println!("{}", "WARNING! The debug build is very slow. Using the release build is highly recommended for large maps.".red());
 
 	env::set_var("RUST_LOG", "info");
-	let args = Args::parse()
-		.map_err(|err| {
-			println!("{}", err);
+	let args = Args::parse_env().unwrap_or_else(|err| err.exit());
+
+	match args.command {
+		Command::Convert { input, output } => convert(input, &output),
+		Command::Query { graph, query, threads, geojson, gpx, stats, snap, metric, profiles, updates, avoid, avoid_bbox } => {
+			let profiles = Arc::new(load_profiles(profiles));
+			let areas = read_areas(avoid, avoid_bbox);
+			let graph = Arc::new(load_graph(graph));
+			let overlay = Arc::new(load_updates(&graph, updates));
+			let area_tree = (!areas.is_empty()).then(|| build_tree(&graph, SnapFilter::All));
+			let blocked = Arc::new(avoided_nodes(&graph, area_tree.as_ref(), &areas));
+			let snap = snap_tree(area_tree, snap);
+			let now = Instant::now();
+			let thread_count = threads.unwrap_or_else(|| min(num_cpus::get() as u32, 4));
+			let options = SolveOptions { snap, track_paths: geojson.is_some() || gpx.is_some(), stats, metric, profiles, overlay, blocked };
+			let results = router::solve_file(graph.clone(), thread_count, query, options);
+			println!("\n");
+			println!(
+				"Calculating the distances took {}{}.",
+				now.elapsed().as_millis(),
+				"ms".green()
+			);
+
+			if let Some(file) = geojson {
+				let features = results
+					.iter()
+					.filter_map(|result| result.path.as_ref())
+					.map(|path| geojson::route_feature(&graph, path))
+					.collect();
+				write_geojson(&file, &geojson::feature_collection(features));
+			}
+
+			if let Some(file) = gpx {
+				write_gpx(&file, &gpx::routes(&graph, results.iter().filter_map(|result| result.path.as_ref())));
+			}
+		}
+		Command::Route { graph, from, to, points, polyline, precision, geojson, gpx, stats, snap, candidates, metric, updates, avoid, avoid_bbox, via } => {
+			let (from, to, via) = match points {
+				// Points between the first and the last one are via points
+				Some(points) => match polyline::decode(&points, precision).as_deref() {
+					Ok([from, via @ .., to]) => (
+						Location::Coords(*from),
+						Location::Coords(*to),
+						via.iter().copied().map(Location::Coords).collect(),
+					),
+					Ok(coords) => {
+						println!("{}", format!("Expected at least 2 points in the polyline, but got {}.", coords.len()).red());
+						exit(-1);
+					}
+					Err(err) => {
+						println!("{}", format!("Invalid polyline: {}", err).red());
+						exit(-1);
+					}
+				},
+				None => (from.unwrap(), to.unwrap(), via),
+			};
+			let areas = read_areas(avoid, avoid_bbox);
+			let graph = load_graph(graph);
+			let overlay = load_updates(&graph, updates);
+			let options = RouteOptions {
+				polyline_precision: polyline.then_some(precision),
+				geojson,
+				gpx,
+				stats,
+				snap,
+				candidates: candidates as usize,
+				metric,
+				overlay,
+				areas,
+				via,
+			};
+			route(&graph, from, to, options)
+		}
+		Command::Tour { graph, start, stops, end, round_trip, heuristic, metric, geojson } => {
+			let kind = match end {
+				_ if round_trip => TourKind::RoundTrip,
+				Some(_) => TourKind::FixedEnd,
+				None => TourKind::Open,
+			};
+			let locations = [vec![start], stops, end.into_iter().collect()].concat();
+			tour(&load_graph(graph), &locations, kind, heuristic, metric, geojson)
+		}
+		Command::Nearest { graph, lat, lon, naive, snap, geojson } => {
+			nearest(&load_graph(graph), lat, lon, naive, snap, geojson)
+		}
+		Command::OneToAll { graph, source, target, metric, updates, geojson, threads, delta, save, pred, csv, tree } => {
+			let graph = load_graph(graph);
+			let overlay = load_updates(&graph, updates);
+			let options = OneToAllOptions { metric, overlay, geojson, parallel: threads.map(|threads| (threads, delta)), save, pred, csv, tree };
+			one_to_all(&graph, source, target, options)
+		}
+		Command::Lookup { distances, targets, path } => lookup(&distances, targets, path),
+		Command::Serve { graph, addr, snap, metric, profiles, updates, avoid, avoid_bbox } => {
+			let profiles = Arc::new(load_profiles(profiles));
+			let areas = read_areas(avoid, avoid_bbox);
+			let graph = Arc::new(load_graph(graph));
+			let overlay = Arc::new(load_updates(&graph, updates));
+			let area_tree = (!areas.is_empty()).then(|| build_tree(&graph, SnapFilter::All));
+			let blocked = Arc::new(avoided_nodes(&graph, area_tree.as_ref(), &areas));
+			let snap = snap_tree(area_tree, snap);
+			let options = SolveOptions { snap, metric, profiles, overlay, blocked, ..SolveOptions::default() };
+			if let Err(err) = server::serve(graph, options, &addr) {
+				println!("{}", format!("The server stopped: {}", err).red());
+				exit(-1);
+			}
+		}
+		Command::Bench { graph, query, count, seed, ranks, algorithm, metric, threads, queue, compact, json } => {
+			if compact && metric != Metric::Cost {
+				println!("{}", "The compact graph only holds the stored costs, '--compact' needs '--metric cost'.".red());
+				exit(-1);
+			}
+			let graph = if compact {
+				BenchGraph::Compact(Arc::new(load_compact_graph(graph)))
+			} else {
+				BenchGraph::Full(Arc::new(load_graph(graph)))
+			};
+			let queries = match query {
+				Some(file) => read_queries(file, graph.node_count()),
+				None if ranks => bench::rank_queries(&graph, metric, count, seed),
+				None => bench::random_queries(graph.node_count(), count, seed),
+			};
+			run_bench(graph, queries, algorithm, metric, &queue, threads, seed, json);
+		}
+		Command::Export { graph, bbox, output } => {
+			let graph = load_graph(graph);
+			write_geojson(&output, &geojson::edges_in_bbox(&graph, &bbox));
+		}
+		Command::Render { graph, bbox, projection, width, from, to, settled, quadtree, output } => {
+			let route = from.zip(to);
+			render(&load_graph(graph), bbox, projection, width, route, settled, quadtree, &output)
+		}
+		Command::Generate { kind, nodes, max_cost, seed, output } => {
+			let graph = kind.generate(nodes, max_cost, seed);
+			println!("Generated {} nodes and {} edges.", graph.node_count(), graph.edge_count());
+			write_graph(&graph, &output);
+		}
+		Command::Extract { graph, bbox, polygon, output, mapping } => extract(&load_graph(graph), bbox, polygon, &output, mapping),
+		Command::Components { graph, largest, mapping } => components(&load_graph(graph), largest, mapping),
+		Command::Validate { graph, limit } => validate(&load_unchecked_graph(graph), limit),
+		Command::Stats { graph, tree, json } => {
+			let graph = load_graph(graph);
+			let tree = tree.then(|| build_tree(&graph, SnapFilter::All));
+			let stats = GraphStats::compute(&graph, tree.as_ref());
+			print!("{}", stats);
+			if let Some(file) = json {
+				write_json(&file, &stats.to_json());
+			}
+		}
+	}
+}
+
+/// Loads the graph and exits if it can't be read or is too broken to search on
+fn load_graph(file: String) -> Graph {
+	let graph = load_unchecked_graph(file);
+	let problems = validate::validate(&graph);
+	let fatal = problems.iter().filter(|problem| problem.is_fatal()).collect::<Vec<_>>();
+	if !fatal.is_empty() {
+		for problem in fatal.iter().take(10) {
+			println!("{}", problem.red());
+		}
+		println!("{}", format!("The graph has {} structural problems, 'validate' lists all of them.", fatal.len()).red());
+		exit(-1);
+	}
+	if !problems.is_empty() {
+		println!("{}", format!("The graph has {} problems which can lead to wrong results, 'validate' lists them.", problems.len()).yellow());
+	}
+	graph
+}
+
+fn load_unchecked_graph(file: String) -> Graph {
+	let now = Instant::now();
+	println!("{}", "Loading graph...".yellow());
+	let graph = Graph::from_file(file).unwrap_or_else(|err| {
+		println!("{}", err.red());
+		exit(-1);
+	});
+	println!(
+		"Loading the graph took {}{}.",
+		now.elapsed().as_millis(),
+		"ms".green()
+	);
+	graph
+}
+
+/// Reads the cost updates of the file, exits if one is invalid
+fn load_updates(graph: &Graph, file: Option<String>) -> CostOverlay {
+	let mut overlay = CostOverlay::default();
+	let Some(file) = file else { return overlay };
+	match overlay.execute_file(graph, &file) {
+		Ok(count) => println!("Applied {} cost updates.", count),
+		Err(err) => {
+			println!("{}", format!("Couldn't apply the cost updates: {}", err).red());
+			exit(-1);
+		}
+	}
+	overlay
+}
+
+/// The boxes and the polygons of the GeoJSON files to avoid, exits if a file can't be read
+fn read_areas(files: Vec<String>, bboxes: Vec<BoundingBox>) -> Vec<Area> {
+	let mut areas = bboxes.into_iter().map(Area::Bbox).collect::<Vec<_>>();
+	for file in files {
+		let polygons = geojson::read_polygons(&file).unwrap_or_else(|err| {
+			println!("{}", format!("Couldn't read the polygon file: {}", err).red());
 			exit(-1);
+		});
+		areas.extend(polygons.into_iter().map(Area::Polygon));
+	}
+	areas
+}
+
+/// Marks the nodes inside the areas, the tree must index all nodes. Empty without a tree.
+fn avoided_nodes(graph: &Graph, tree: Option<&NodeTree>, areas: &[Area]) -> Vec<bool> {
+	let Some(tree) = tree else { return Vec::new() };
+	let blocked = avoid::blocked_nodes(graph, tree, areas);
+	println!("Avoiding {} nodes in {} areas.", blocked.iter().filter(|blocked| **blocked).count(), areas.len());
+	blocked
+}
+
+/// Snaps with a tree built on the first coordinate, or with the tree of all nodes built for the
+/// avoided areas if nothing is filtered
+fn snap_tree(area_tree: Option<NodeTree>, snap: SnapFilter) -> SnapTree {
+	match area_tree {
+		Some(tree) if snap == SnapFilter::All => SnapTree::with_tree(snap, tree),
+		_ => SnapTree::new(snap),
+	}
+}
+
+fn build_tree(graph: &Graph, snap: SnapFilter) -> NodeTree {
+	build_masked_tree(graph, &snap.mask(graph))
+}
+
+/// Builds the nearest data structure over the nodes marked in `mask`
+fn build_masked_tree(graph: &Graph, mask: &[bool]) -> NodeTree {
+	let now = Instant::now();
+	println!("{}", "Building nearest data structure... ".yellow());
+	let tree = NodeTree::build_filtered(graph.nodes(), |i| mask[i]);
+	println!(
+		"Building the data structure took {}{}.",
+		now.elapsed().as_millis(),
+		"ms".green()
+	);
+	tree
+}
+
+fn write_geojson(file: &str, value: &serde_json::Value) {
+	if let Err(err) = geojson::write_file(file, value) {
+		println!("{}", format!("Couldn't write the GeoJSON file: {}", err).red());
+		exit(-1);
+	}
+	println!("Wrote {}.", file);
+}
+
+fn write_json(file: &str, value: &serde_json::Value) {
+	if let Err(err) = fs::write(file, serde_json::to_string_pretty(value).unwrap()) {
+		println!("{}", format!("Couldn't write the JSON file: {}", err).red());
+		exit(-1);
+	}
+	println!("Wrote {}.", file);
+}
+
+fn write_gpx(file: &str, gpx: &str) {
+	if let Err(err) = gpx::write_file(file, gpx) {
+		println!("{}", format!("Couldn't write the GPX file: {}", err).red());
+		exit(-1);
+	}
+	println!("Wrote {}.", file);
+}
+
+/// Turns locations into node ids. The nearest data structure is only built if coordinates are given
+/// and it wasn't built before.
+fn resolve_locations(graph: &Graph, tree: &mut Option<NodeTree>, snap: SnapFilter, locations: &[Location]) -> Vec<usize> {
+	resolve_candidates(graph, tree, snap, locations, 1)
+		.into_iter()
+		.map(|candidates| candidates[0])
+		.collect()
+}
+
+/// Like `resolve_locations`, but coordinates are snapped to up to `count` nodes, the nearest first
+fn resolve_candidates(
+	graph: &Graph,
+	tree: &mut Option<NodeTree>,
+	snap: SnapFilter,
+	locations: &[Location],
+	count: usize,
+) -> Vec<Vec<usize>> {
+	if tree.is_none() && locations.iter().any(|location| matches!(location, Location::Coords(_))) {
+		*tree = Some(build_tree(graph, snap));
+	}
+
+	locations
+		.iter()
+		.map(|location| match location {
+			Location::Node(node) => {
+				if *node >= graph.node_count() {
+					println!("{}", format!("Invalid node id {}.", node).red());
+					exit(-1);
+				}
+				vec![*node]
+			}
+			Location::Coords(coords) => {
+				let tree = tree.as_ref().unwrap();
+				if tree.is_empty() {
+					println!("{}", format!("There is no node to snap {} to.", coords).red());
+					exit(-1);
+				}
+				tree.nearest_nodes(graph.nodes(), *coords, count)
+			}
 		})
-		.unwrap();
+		.collect()
+}
 
-	if let Some(file) = args.graph_file {
-		let now = Instant::now();
-		println!("{}", "Loading graph...".yellow());
-		let graph = Graph::from_file(file);
-		println!(
-			"Loading the graph took {}{}.",
-			now.elapsed().as_millis(),
-			"ms".green()
-		);
+fn convert(input: String, output: &str) {
+	write_graph(&load_graph(input), output);
+}
+
+/// Writes the binary format if the file ends with '.bin' and FMI otherwise
+fn write_graph(graph: &Graph, output: &str) {
+	let now = Instant::now();
+	println!("{}", format!("Writing graph to {}...", output).yellow());
+	let result = if output.ends_with(".bin") {
+		graph.write_binary_file(output)
+	} else {
+		graph.write_fmi_file(output)
+	};
+
+	if let Err(err) = result {
+		println!("{}", format!("Couldn't write the graph file: {}", err).red());
+		exit(-1);
+	}
+	println!(
+		"Writing the graph took {}{}.",
+		now.elapsed().as_millis(),
+		"ms".green()
+	);
+}
 
+fn components(graph: &Graph, largest: Option<String>, mapping: Option<String>) {
+	let now = Instant::now();
+	println!("{}", "Computing strongly connected components...".yellow());
+	let components = components::strongly_connected(graph);
+	println!("Computing the components took {}{}.", now.elapsed().as_millis(), "ms".green());
+
+	let Some(largest_component) = components.largest() else {
+		println!("The graph is empty.");
+		return;
+	};
+	let largest_size = components.sizes[largest_component];
+	println!("Components: {}", components.count());
+	println!(
+		"Largest component: {} nodes ({:.2}%)",
+		largest_size,
+		largest_size as f64 * 100.0 / graph.node_count() as f64
+	);
+	for (bucket, count) in components.size_histogram().iter().enumerate() {
+		let from = 10usize.pow(bucket as u32);
+		println!("{:>10} - {:<10} nodes: {} components", from, from * 10 - 1, count);
+	}
+
+	if let Some(file) = largest {
+		let (graph, node_mapping) = graph.restrict_to(&components.members(largest_component));
+		write_graph(&graph, &file);
+		if let Some(file) = mapping {
+			write_mapping(&node_mapping, &file);
+		}
+	}
+}
+
+fn write_mapping(mapping: &NodeMapping, file: &str) {
+	if let Err(err) = mapping.write_file(file) {
+		println!("{}", format!("Couldn't write the mapping file: {}", err).red());
+		exit(-1);
+	}
+	println!("Wrote {}.", file);
+}
+
+fn extract(graph: &Graph, bbox: Option<BoundingBox>, polygon: Option<String>, output: &str, mapping: Option<String>) {
+	let keep = if let Some(file) = polygon {
+		let polygons = geojson::read_polygons(&file).unwrap_or_else(|err| {
+			println!("{}", format!("Couldn't read the polygon file: {}", err).red());
+			exit(-1);
+		});
+		graph.nodes().iter().map(|node| polygons.iter().any(|polygon| polygon.contains(&node.coords))).collect::<Vec<_>>()
+	} else {
+		let bbox = bbox.expect("Either a bounding box or a polygon is required");
+		graph.nodes().iter().map(|node| bbox.contains(&node.coords)).collect()
+	};
+
+	let (subgraph, node_mapping) = graph.restrict_to(&keep);
+	println!("Extracted {} of {} nodes and {} of {} edges.", subgraph.node_count(), graph.node_count(), subgraph.edge_count(), graph.edge_count());
+	write_graph(&subgraph, output);
+	if let Some(file) = mapping {
+		write_mapping(&node_mapping, &file);
+	}
+}
+
+fn validate(graph: &Graph, limit: usize) {
+	let problems = validate::validate(graph);
+	if problems.is_empty() {
+		println!("{}", "The graph is valid.".green());
+		return;
+	}
+
+	for problem in problems.iter().take(limit) {
+		println!("{}", problem.red());
+	}
+	if problems.len() > limit {
+		println!("... and {} more", problems.len() - limit);
+	}
+	println!("{}", format!("Found {} problems.", problems.len()).red());
+	exit(-1);
+}
+
+fn nearest(graph: &Graph, lat: f64, lon: f64, naive: bool, snap: SnapFilter, geojson: Option<String>) {
+	let mask = snap.mask(graph);
+	let tree = build_masked_tree(graph, &mask);
+	let coordinates = Coords::deg(lat, lon);
+	if tree.is_empty() {
+		println!("{}", "No node passes the snap filter.".red());
+		exit(-1);
+	}
+
+	if naive {
+		// The naive search looks at the same nodes as the tree
+		let indices = (0..graph.node_count()).filter(|i| mask[*i]).collect::<Vec<_>>();
+		print!("Finding nearest node (naïve)... ");
 		let now = Instant::now();
-		println!("{}", "Building nearest data structure... ".yellow());
-		let tree = NodeTree::build(graph.nodes());
+		let nearest = Graph::nearest_node_naive_indices(graph.nodes(), &indices, coordinates);
+		println!("   {}{}", now.elapsed().as_millis(), "ms".green());
 		println!(
-			"Building the data structure took {}{}.",
-			now.elapsed().as_millis(),
-			"ms".green()
+			"Naïve nearest node to {}, {}:       [{}] {}.",
+			lat,
+			lon,
+			nearest,
+			graph.node(nearest).unwrap()
 		);
+	}
 
-		if let (Some(lat), Some(lon)) = (args.lat, args.lon) {
-			let coordinates = Coords::deg(lat, lon);
-
-			if (args.flags & args::flag::SHOW_NAIVE_NODE) != 0 {
-				print!("Finding nearest node (naïve)... ");
-				let now = Instant::now();
-				let nearest = Graph::nearest_node_naive(graph.nodes(), coordinates);
-				println!("   {}{}", now.elapsed().as_millis(), "ms".green());
-				println!(
-					"Naïve nearest node to {}, {}:       [{}] {}.",
-					lat,
-					lon,
-					nearest,
-					graph.node(nearest).unwrap()
-				);
-			}
+	print!("Finding nearest node (QuadTree)... ");
+	let now = Instant::now();
+	let nearest = tree.nearest_node(graph.nodes(), coordinates);
+	println!("{}{}", now.elapsed().as_micros(), "µs".red());
+	println!(
+		"Nearest node to {}, {}:             [{}] {}.",
+		lat,
+		lon,
+		nearest,
+		graph.node(nearest).unwrap()
+	);
 
-			print!("Finding nearest node (QuadTree)... ");
-			let now = Instant::now();
-			let nearest = tree.nearest_node(graph.nodes(), coordinates);
-			println!("{}{}", now.elapsed().as_micros(), "µs".red());
-			println!(
-				"Nearest node to {}, {}:             [{}] {}.",
-				lat,
-				lon,
-				nearest,
-				graph.node(nearest).unwrap()
-			);
+	if let Some(file) = geojson {
+		let features = geojson::snapped_features(graph, coordinates, nearest);
+		write_geojson(&file, &geojson::feature_collection(features));
+	}
+}
+
+struct OneToAllOptions {
+	metric: Metric,
+	/// Cost updates applied on top of the metric
+	overlay: CostOverlay,
+	geojson: Option<String>,
+	/// The thread count and bucket width of delta-stepping, dijkstra is used without it
+	parallel: Option<(u32, Option<i64>)>,
+	save: Option<String>,
+	/// Whether the predecessors are saved as well
+	pred: bool,
+	csv: Option<String>,
+	/// The GeoJSON file for the shortest-path tree
+	tree: Option<String>,
+}
+
+/// Without a source the search runs backwards from the target, which gives the distances from all
+/// nodes to the target. The node asked for on the console is the source then.
+fn one_to_all(graph: &Graph, source: Option<usize>, target: Option<usize>, options: OneToAllOptions) {
+	let incoming;
+	let (search_direction, root, other, reverse) = match source {
+		Some(source) => (Direction::Forward, source, target, false),
+		None => {
+			incoming = IncomingEdges::new(graph);
+			(Direction::Backward(&incoming), target.expect("Either a source or a target is required"), None, true)
 		}
+	};
+	let costs = Overlaid::new(&options.metric, &options.overlay);
+	let (root_name, other_name, direction) = if reverse { ("target", "source", "all-to-one") } else { ("source", "target", "one-to-all") };
+	if root >= graph.node_count() {
+		println!("{}", format!("The {} {} is not a node of the graph.", root_name, root).red());
+		exit(-1);
+	}
 
-		let arc = Arc::new(graph);
+	let now = Instant::now();
+	// Dijkstra builds the whole shortest-path tree, delta-stepping only the distances
+	let (dist, tree) = match options.parallel {
+		Some((threads, delta)) => {
+			let delta = delta.unwrap_or_else(|| delta::default_delta(graph, &costs));
+			println!("{}", format!("Running {} delta-stepping with {} threads and delta {}...", direction, threads, delta).yellow());
+			let dist = delta::shortest_paths(graph, &costs, root, search_direction, delta, threads as usize).unwrap_or_else(|err| {
+				println!("{}", err.red());
+				exit(-1);
+			});
+			(dist, None)
+		}
+		None => {
+			println!("{}", format!("Running {} dijkstra...", direction).yellow());
+			(Vec::new(), Some(router::shortest_path_tree_with(graph, &costs, root, search_direction)))
+		}
+	};
+	println!(
+		"The {} search took {}{}.",
+		direction,
+		now.elapsed().as_millis(),
+		"ms".green()
+	);
 
-		if let Some(query) = args.query_file {
-			let now = Instant::now();
-			let thread_count = args.thread_count.unwrap_or_else(|| min(num_cpus::get() as u32, 4));
-			router::solve_file(arc.clone(), thread_count, query);
-			println!("\n");
-			println!(
-				"Calculating the distances took {}{}.",
-				now.elapsed().as_millis(),
-				"ms".green()
-			);
+	if let (Some(file), Some(tree)) = (&options.tree, &tree) {
+		write_geojson(file, &geojson::tree_features(graph, tree));
+	}
+
+	// Saved results are looked up later, so there is no need to ask for a node
+	let other = match other {
+		None if options.save.is_some() || options.csv.is_some() || options.tree.is_some() => None,
+		Some(other) => Some(other),
+		None => {
+			println!("Enter {} node:", other_name);
+			let mut line = String::new();
+			io::stdin().read_line(&mut line).unwrap();
+			Some(line.trim()
+				.parse::<usize>()
+				.unwrap_or_else(|_| panic!("{} node id must be a positive number.", other_name)))
 		}
+	};
 
-		if let Some(source) = args.source_node {
-			let now = Instant::now();
-			println!("{}", "Running one-to-all dijkstra...".yellow());
-			let paths = router::shortest_paths(arc.as_ref(), source as usize);
-			println!(
-				"The one-to-all dijkstra took {}{}.",
-				now.elapsed().as_millis(),
-				"ms".green()
-			);
+	if let Some(other) = other {
+		assert!(other < graph.node_count(), "Invalid node id");
+		let distance = tree.as_ref().map_or(dist.get(other), |tree| tree.dist.get(other)).copied()
+			.filter(|dist| *dist != EdgeCost::MAX);
+		if reverse {
+			println!("Distance from source[{}] to target[{}]: {}.", other, root, distance.unwrap_or(-1));
+		} else {
+			println!("Distance to target[{}]: {}.", other, distance.unwrap_or(-1));
+		}
 
-			let target = match args.target_node {
-				Some(target) => target as usize,
-				None => {
-					println!("Enter target node:");
-					let mut line = String::new();
-					io::stdin().read_line(&mut line).unwrap();
-					line[0..line.len() - 1]
-						.parse::<usize>()
-						.expect("Target node id must be a positive number.")
+		let path = match &tree {
+			Some(tree) => {
+				if distance.is_some() {
+					println!("The subtree of the {} has {} nodes.", other_name, tree.subtree(graph, other).len());
 				}
-			};
+				tree.path_to(graph, other)
+			}
+			None => {
+				let (start, goal) = if reverse { (other, root) } else { (root, other) };
+				let mut trace = SearchTrace::default().with_pred();
+				let cost = router::shortest_path_with(graph, &costs, start, goal, &mut trace);
+				(cost >= 0).then(|| trace.path_to(graph, start, goal))
+			}
+		};
+		if let Some(file) = &options.geojson {
+			let features = path.iter().map(|path| geojson::route_feature(graph, path)).collect();
+			write_geojson(file, &geojson::feature_collection(features));
+		}
+	}
 
-			let mut dist = *paths.get(target).expect("Invalid node id");
+	if options.save.is_some() || options.csv.is_some() {
+		let table = match tree {
+			Some(tree) => DistanceTable::new(graph, root, reverse, tree.dist, options.pred.then_some(tree.parent_edges)),
+			None => DistanceTable::new(graph, root, reverse, dist, None),
+		};
+		if let Some(file) = &options.save {
+			if let Err(err) = table.write_file(file) {
+				println!("{}", format!("Couldn't write the distance file: {}", err).red());
+				exit(-1);
+			}
+			println!("Wrote {}.", file);
+		}
+		if let Some(file) = &options.csv {
+			let result = File::create(file).and_then(|file| {
+				let mut writer = BufWriter::new(file);
+				table.write_csv(graph, &mut writer)?;
+				writer.flush()
+			});
+			if let Err(err) = result {
+				println!("{}", format!("Couldn't write the CSV file: {}", err).red());
+				exit(-1);
+			}
+			println!("Wrote {}.", file);
+		}
+	}
+}
+
+fn lookup(file: &str, targets: Vec<usize>, path: bool) {
+	let table = DistanceTable::read_file(file).unwrap_or_else(|err| {
+		println!("{}", format!("Couldn't read the distance file: {}", err).red());
+		exit(-1);
+	});
+	if path && table.pred.is_none() {
+		println!("{}", "The distance file has no predecessors, save it with 'one-to-all --pred' to look up paths.".red());
+		exit(-1);
+	}
+	// The nodes looked up are the sources if the distances lead to a target
+	let name = if table.reverse {
+		println!("Distances from {} nodes to node {}.", table.dist.len(), table.source);
+		"source"
+	} else {
+		println!("Distances from node {} to {} nodes.", table.source, table.dist.len());
+		"target"
+	};
 
-			if dist == NodeIndex::MAX {
-				dist = -1;
+	let print = |node: usize| match table.dist.get(node) {
+		Some(_) => {
+			println!("Distance {} {}[{}]: {}.", if table.reverse { "from" } else { "to" }, name, node, table.distance(node).unwrap_or(-1));
+			if let (true, Some(nodes)) = (path, table.path(node)) {
+				let nodes = nodes.iter().map(|node| node.to_string()).collect::<Vec<_>>();
+				println!("Path: {}.", nodes.join(" -> "));
 			}
+		}
+		None => println!("{}", format!("{} is not a node of the graph.", node).red()),
+	};
+	if !targets.is_empty() {
+		targets.into_iter().for_each(print);
+		return;
+	}
 
-			println!("Distance to target[{}]: {}.", target, dist);
+	println!("Enter {} nodes, one per line:", name);
+	for line in io::stdin().lock().lines() {
+		match line.unwrap().trim().parse::<usize>() {
+			Ok(node) => print(node),
+			Err(_) => println!("{}", format!("The {} node id must be a positive number.", name).red()),
 		}
+	}
+}
+
+struct RouteOptions {
+	polyline_precision: Option<u32>,
+	geojson: Option<String>,
+	gpx: Option<String>,
+	stats: bool,
+	snap: SnapFilter,
+	/// How many nearest nodes are tried for coordinates
+	candidates: usize,
+	metric: Metric,
+	/// Cost updates applied on top of the metric
+	overlay: CostOverlay,
+	/// The zones the route must not enter
+	areas: Vec<Area>,
+	/// The locations to pass between the start and the end, in order
+	via: Vec<Location>,
+}
+
+fn route(graph: &Graph, from: Location, to: Location, options: RouteOptions) {
+	let RouteOptions { polyline_precision, geojson, gpx, stats, snap, candidates, metric, overlay, areas, via } = options;
+	let locations = [vec![from], via, vec![to]].concat();
+	// Avoiding areas needs a tree of all nodes, which can also be used for snapping if nothing is filtered
+	let mut area_tree = (!areas.is_empty()).then(|| build_tree(graph, SnapFilter::All));
+	let blocked = avoided_nodes(graph, area_tree.as_ref(), &areas);
+	let mut snap_tree = None;
+	let tree = if snap == SnapFilter::All { &mut area_tree } else { &mut snap_tree };
+	let candidates = resolve_candidates(graph, tree, snap, &locations, candidates);
+	let overlaid = Overlaid::new(&metric, &overlay);
+	let costs = Avoid::new(&overlaid, &blocked);
+	for node in candidates.iter().map(|candidates| candidates[0]).filter(|node| costs.is_blocked(*node)) {
+		println!("{}", format!("The node [{}] lies in an avoided area.", node).red());
+	}
+
+	let now = Instant::now();
+	let mut trace = SearchTrace::default().with_pred();
+	if stats {
+		trace = trace.with_stats();
+	}
+	// Only routes without via points fall back to further candidates
+	let result = if let [starts, goals] = &candidates[..] {
+		router::shortest_path_fallback(graph, &costs, starts, goals, &mut trace)
+			.map(|(start, goal, cost)| (vec![start, goal], vec![cost], trace.path_to(graph, start, goal)))
+			.ok_or(0)
 	} else {
-		println!("Nothing to do!\n{}", "Use '--help' for help.".yellow());
+		let waypoints = candidates.iter().map(|candidates| candidates[0]).collect::<Vec<_>>();
+		router::via_route(graph, &costs, &waypoints, &mut trace)
+			.map(|route| (waypoints, route.legs.iter().map(|leg| leg.cost).collect(), route.path().expect("The predecessors are recorded")))
+	};
+	println!("Routing took {}{}.", now.elapsed().as_millis(), "ms".green());
+	if let Some(stats) = &trace.stats {
+		println!("Search space: {}.", stats);
+	}
+
+	let (waypoints, legs, path) = match result {
+		Ok(result) => result,
+		Err(leg) => {
+			println!("There is no route from [{}] to [{}].", candidates[leg][0], candidates[leg + 1][0]);
+			return;
+		}
+	};
+	let (start, goal) = (waypoints[0], waypoints[waypoints.len() - 1]);
+	if waypoints.len() == 2 && (start, goal) != (candidates[0][0], candidates[1][0]) {
+		println!("Fell back to the snapped nodes [{}] and [{}].", start, goal);
+	}
+	if legs.len() > 1 {
+		for (i, (pair, cost)) in waypoints.windows(2).zip(&legs).enumerate() {
+			println!("Leg {} from [{}] to [{}]: {}.", i + 1, pair[0], pair[1], metric.format(*cost));
+		}
+	}
+	let cost = legs.iter().sum();
+	println!("{} from [{}] to [{}]: {} over {} edges.", metric.name(), start, goal, metric.format(cost), path.path.len());
+
+	if let Some(precision) = polyline_precision {
+		let mut nodes = path.nodes();
+		if nodes.is_empty() {
+			nodes.push(start as NodeIndex);
+		}
+		let coords = nodes.iter().map(|node| graph.node(*node as usize).unwrap().coords).collect::<Vec<_>>();
+		println!("Polyline: {}", polyline::encode(&coords, precision));
+	}
+
+	if let Some(file) = geojson {
+		let mut features = vec![geojson::route_feature(graph, &path)];
+		for (location, node) in locations.iter().zip(waypoints) {
+			match location {
+				Location::Coords(coords) => features.extend(geojson::snapped_features(graph, *coords, node)),
+				Location::Node(_) => features.push(geojson::node_feature(graph, node)),
+			}
+		}
+		write_geojson(&file, &geojson::feature_collection(features));
+	}
+
+	if let Some(file) = gpx {
+		write_gpx(&file, &gpx::route(graph, &path, start, goal));
+	}
+}
+
+/// Orders the stops between the first and, for `TourKind::FixedEnd`, the last location
+fn tour(graph: &Graph, locations: &[Location], kind: TourKind, heuristic: bool, metric: Metric, geojson: Option<String>) {
+	let nodes = resolve_locations(graph, &mut None, SnapFilter::All, locations);
+
+	let now = Instant::now();
+	let matrix = tsp::cost_matrix(graph, &metric, &nodes);
+	println!("Computing the {0}x{0} cost matrix took {1}{2}.", nodes.len(), now.elapsed().as_millis(), "ms".green());
+
+	let now = Instant::now();
+	let Some(tour) = tsp::solve(&matrix, kind, heuristic) else {
+		println!("{}", "Not all stops can be reached from each other.".red());
+		return;
+	};
+	let method = if tour.exact { "exact" } else { "heuristic" };
+	println!("Finding the {} order took {}{}.", method, now.elapsed().as_millis(), "ms".green());
+
+	let mut order = tour.order.clone();
+	if kind == TourKind::RoundTrip {
+		order.push(0);
+	}
+	let waypoints = order.iter().map(|i| nodes[*i]).collect::<Vec<_>>();
+	for (i, pair) in order.windows(2).enumerate() {
+		println!("{:>3}. [{}] -> [{}]: {}", i + 1, nodes[pair[0]], nodes[pair[1]], metric.format(matrix[pair[0]][pair[1]]));
+	}
+	println!("Total ({} tour): {}.", kind, metric.format(tour.cost));
+
+	if let Some(file) = geojson {
+		let route = router::via_route(graph, &metric, &waypoints, &mut SearchTrace::default().with_pred())
+			.expect("The tour only uses reachable legs");
+		let mut features = vec![geojson::route_feature(graph, &route.path().expect("The predecessors are recorded"))];
+		features.extend(nodes.iter().map(|node| geojson::node_feature(graph, *node)));
+		write_geojson(&file, &geojson::feature_collection(features));
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render(
+	graph: &Graph,
+	bbox: Option<BoundingBox>,
+	projection: Projection,
+	width: u32,
+	route: Option<(Location, Location)>,
+	settled: bool,
+	quadtree: bool,
+	output: &str,
+) {
+	let bbox = bbox.unwrap_or_else(|| {
+		BoundingBox::around(graph.nodes().iter().map(|node| &node.coords)).expect("The graph loaded is empty!")
+	});
+	let mut tree = quadtree.then(|| build_tree(graph, SnapFilter::All));
+
+	let now = Instant::now();
+	println!("{}", "Rendering...".yellow());
+	let mut renderer = SvgRenderer::new(bbox, projection, width);
+	renderer.draw_edges(graph);
+	if let Some(tree) = &tree {
+		renderer.draw_quadrants(tree);
+	}
+
+	if let Some((from, to)) = route {
+		let nodes = resolve_locations(graph, &mut tree, SnapFilter::All, &[from, to]);
+		let mut trace = SearchTrace::default().with_pred();
+		if settled {
+			trace = trace.with_settled();
+		}
+
+		let cost = router::shortest_path_traced(graph, nodes[0], nodes[1], &mut trace);
+		if let Some(settled) = &trace.settled {
+			println!("Dijkstra settled {} nodes.", settled.len());
+			renderer.draw_nodes(graph, settled);
+		}
+		if cost < 0 {
+			println!("There is no route from [{}] to [{}].", nodes[0], nodes[1]);
+		} else {
+			renderer.draw_path(graph, &trace.path_to(graph, nodes[0], nodes[1]));
+		}
+	}
+
+	if let Err(err) = renderer.write_file(output) {
+		println!("{}", format!("Couldn't write the SVG file: {}", err).red());
+		exit(-1);
+	}
+	println!("Rendering took {}{}.", now.elapsed().as_millis(), "ms".green());
+}
+
+/// Reads `<source node id> <target node id>` lines, exits on the first line that isn't one or names
+/// a node the graph doesn't have
+fn read_queries(file: String, node_count: usize) -> Vec<BenchQuery> {
+	let file = File::open(file).unwrap_or_else(|err| {
+		println!("{}", format!("Couldn't open the query file: {}", err).red());
+		exit(-1);
+	});
+	let mut queries = Vec::new();
+	for (i, line) in BufReader::new(file).lines().map(|line| line.unwrap()).enumerate() {
+		if line.trim().is_empty() {
+			continue;
+		}
+		match router::parse_query(&line) {
+			Some((start, goal)) if start < node_count && goal < node_count => queries.push(BenchQuery { start, goal, rank: None }),
+			Some(_) => {
+				println!("{}", format!("Line {}: '{}' names a node which doesn't exist, the graph has {} nodes.", i + 1, line, node_count).red());
+				exit(-1);
+			}
+			None => {
+				println!("{}", format!("Line {}: '{}' is not a query, expected '<source node id> <target node id>'.", i + 1, line).red());
+				exit(-1);
+			}
+		}
+	}
+	queries
+}
+
+/// The built-in profiles and the ones of the config file, exits if it is invalid
+fn load_profiles(file: Option<String>) -> Profiles {
+	let Some(file) = file else { return Profiles::default() };
+	Profiles::read_file(&file).unwrap_or_else(|err| {
+		println!("{}", format!("Couldn't read the profile file: {}", err).red());
+		exit(-1);
+	})
+}
+
+/// Loads the graph straight into the compact representation or exits if it doesn't fit into 32 bits
+fn load_compact_graph(file: String) -> CompactGraph {
+	let now = Instant::now();
+	println!("{}", "Loading compact graph...".yellow());
+	let compact = CompactGraph::from_file(file).unwrap_or_else(|err| {
+		println!("{}", format!("The graph doesn't fit into the compact representation: {}", err).red());
+		exit(-1);
+	});
+	println!(
+		"Loading the graph took {}{}.",
+		now.elapsed().as_millis(),
+		"ms".green()
+	);
+	let full_bytes = compact.node_count() * size_of::<Node>() + compact.edge_count() * size_of::<Edge>();
+	println!(
+		"The compact graph takes {:.1} MiB instead of {:.1} MiB.",
+		compact.memory_size() as f64 / (1024.0 * 1024.0),
+		full_bytes as f64 / (1024.0 * 1024.0)
+	);
+	compact
+}
+
+/// Runs the queries once per priority queue. The JSON file holds a single report, or an array if
+/// several queues were compared.
+#[allow(clippy::too_many_arguments)]
+fn run_bench(graph: BenchGraph, queries: Vec<BenchQuery>, algorithm: Algorithm, metric: Metric, queues: &[QueueKind], threads: u32, seed: u64, json: Option<String>) {
+	if graph.node_count() == 0 {
+		println!("{}", "The graph has no nodes to run queries between.".red());
+		exit(-1);
+	}
+	// The radix heap relies on popped keys never decreasing, only stored costs can be negative
+	if queues.contains(&QueueKind::Radix) && metric == Metric::Cost && graph.has_negative_costs() {
+		println!("{}", "The radix queue doesn't support graphs with negative costs.".red());
+		exit(-1);
+	}
+	let queries = Arc::new(queries);
+	let mut reports = Vec::new();
+	for queue in queues {
+		println!(
+			"{}",
+			format!("Running {} queries with {} and a {} queue on {} threads...", queries.len(), algorithm, queue, threads).yellow()
+		);
+		let report = bench::run(graph.clone(), queries.clone(), algorithm, metric, *queue, threads, seed);
+
+		let print = |name: String, summary: &bench::Summary| {
+			println!(
+				"{:>8} {:>8} queries   mean {:>10.1}µs   median {:>10.1}µs   p99 {:>10.1}µs",
+				name, summary.count, summary.mean, summary.median, summary.p99
+			);
+		};
+		for (rank, summary) in &report.ranks {
+			print(format!("2^{}", rank), summary);
+		}
+		print("total".to_string(), &report.total);
+		println!(
+			"Throughput: {:.1} queries/s, wall time {}{}.",
+			report.throughput(),
+			report.wall_time.as_millis(),
+			"ms".green()
+		);
+		reports.push(report);
+	}
+
+	if reports.len() > 1 {
+		let fastest = reports.iter().max_by(|a, b| a.throughput().total_cmp(&b.throughput())).unwrap();
+		println!("{}", format!("The {} queue is the fastest.", fastest.queue).green());
+	}
+
+	if let Some(file) = json {
+		let value = match &reports[..] {
+			[report] => report.to_json(),
+			_ => serde_json::Value::Array(reports.iter().map(|report| report.to_json()).collect()),
+		};
+		write_json(&file, &value);
 	}
 }
@@ -19,7 +19,10 @@ impl Node {
     pub fn new(coords: Coords, offset: i64) -> Self {
         Self { coords, offset }
     }
-    pub fn default() -> Self {
+}
+
+impl Default for Node {
+    fn default() -> Self {
         Self { coords: Coords::default() , offset: NodeIndex::MAX }
     }
 }
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::cost::CostFunction;
+use crate::edge::EdgeCost;
+use crate::Graph;
+
+/// A change of the cost of an edge which dijkstra applies at query time
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CostUpdate {
+    /// The edge can't be used, e.g. because the road is closed
+    Block,
+    /// The cost is multiplied, e.g. by 1.5 for a traffic jam
+    Multiply(f64),
+}
+
+/// The cost updates of a graph by edge index. An edge has at most one update, a new one replaces it.
+#[derive(Clone, Default, Debug)]
+pub struct CostOverlay {
+    updates: HashMap<usize, CostUpdate>,
+}
+
+impl CostOverlay {
+    pub fn is_empty(&self) -> bool { self.updates.is_empty() }
+    pub fn len(&self) -> usize { self.updates.len() }
+    pub fn get(&self, edge: usize) -> Option<CostUpdate> { self.updates.get(&edge).copied() }
+
+    pub fn set(&mut self, edge: usize, update: CostUpdate) {
+        self.updates.insert(edge, update);
+    }
+
+    /// Gives the edge its original cost back
+    pub fn restore(&mut self, edge: usize) {
+        self.updates.remove(&edge);
+    }
+
+    pub fn reset(&mut self) {
+        self.updates.clear();
+    }
+
+    /// The cost of an edge after its update, `None` if it is blocked. Multiplied costs saturate at the
+    /// bounds of `EdgeCost`.
+    pub fn apply(&self, edge: usize, cost: EdgeCost) -> Option<EdgeCost> {
+        if self.updates.is_empty() {
+            return Some(cost);
+        }
+        match self.updates.get(&edge) {
+            None => Some(cost),
+            Some(CostUpdate::Block) => None,
+            Some(CostUpdate::Multiply(factor)) => Some((cost as f64 * factor).round() as EdgeCost),
+        }
+    }
+
+    /// Runs one command: 'block <edge>', 'multiply <edge> <factor>', 'restore <edge>' or 'reset'. An
+    /// edge is either given by its index or as '<source node id>,<target node id>', which affects all
+    /// edges between the two nodes.
+    pub fn execute(&mut self, graph: &Graph, command: &str) -> Result<(), String> {
+        let words = command.split_whitespace().collect::<Vec<_>>();
+        match words[..] {
+            ["reset"] => self.reset(),
+            ["block", edge] => edges(graph, edge)?.into_iter().for_each(|edge| self.set(edge, CostUpdate::Block)),
+            ["restore", edge] => edges(graph, edge)?.into_iter().for_each(|edge| self.restore(edge)),
+            ["multiply", edge, factor] => {
+                let factor = factor.parse::<f64>().ok().filter(|factor| factor.is_finite() && *factor >= 0.0)
+                    .ok_or_else(|| format!("The factor must be a non-negative number but got '{}'", factor))?;
+                edges(graph, edge)?.into_iter().for_each(|edge| self.set(edge, CostUpdate::Multiply(factor)));
+            }
+            _ => return Err(format!("Expected 'block <edge>', 'multiply <edge> <factor>', 'restore <edge>' or 'reset' but got '{}'", command)),
+        }
+        Ok(())
+    }
+
+    /// Runs the commands of a file line by line, skipping empty lines and '#' comments. Returns the
+    /// number of commands, or the first error with its line number.
+    pub fn execute_file(&mut self, graph: &Graph, path: &str) -> Result<usize, String> {
+        let file = File::open(path).map_err(|err| err.to_string())?;
+        let mut count = 0;
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|err| err.to_string())?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.execute(graph, line).map_err(|err| format!("Line {}: {}", i + 1, err))?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Whether a line is an update command rather than a query, which starts with a node id or a coordinate
+pub fn is_command(line: &str) -> bool {
+    line.trim_start().starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+/// Wraps a cost function and applies the cost updates of an overlay on top of it
+pub struct Overlaid<'a> {
+    costs: &'a dyn CostFunction,
+    overlay: &'a CostOverlay,
+}
+
+impl<'a> Overlaid<'a> {
+    pub fn new(costs: &'a dyn CostFunction, overlay: &'a CostOverlay) -> Self {
+        Self { costs, overlay }
+    }
+}
+
+impl CostFunction for Overlaid<'_> {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        self.overlay.apply(edge, self.costs.cost(graph, edge)).unwrap_or(EdgeCost::MAX)
+    }
+
+    fn allows(&self, graph: &Graph, edge: usize) -> bool {
+        self.overlay.get(edge) != Some(CostUpdate::Block) && self.costs.allows(graph, edge)
+    }
+}
+
+/// The indices of the edges given by '<index>' or '<source node id>,<target node id>'
+fn edges(graph: &Graph, edge: &str) -> Result<Vec<usize>, String> {
+    let Some((src, trg)) = edge.split_once(',') else {
+        return match edge.parse::<usize>() {
+            Ok(index) if index < graph.edge_count() => Ok(vec![index]),
+            _ => Err(format!("'{}' is not an edge index", edge)),
+        };
+    };
+
+    let (Ok(src), Ok(trg)) = (src.parse::<usize>(), trg.parse::<usize>()) else {
+        return Err(format!("Expected '<source node id>,<target node id>' but got '{}'", edge));
+    };
+    let edges = if src < graph.node_count() {
+        graph.outgoing_edge_range(src).filter(|index| graph.edges()[*index].trg as usize == trg).collect()
+    } else {
+        Vec::new()
+    };
+    if edges.is_empty() {
+        return Err(format!("There is no edge from {} to {}", src, trg));
+    }
+    Ok(edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_command, CostOverlay, CostUpdate, Overlaid};
+    use crate::cost::StoredCost;
+    use crate::edge::EdgeCost;
+    use crate::generate::fixture;
+    use crate::router::{self, SearchTrace};
+
+    #[test]
+    fn updates() {
+        let graph = fixture("detour");
+        let route = |overlay: &CostOverlay| router::shortest_path_with(&graph, &Overlaid::new(&StoredCost, overlay), 0, 2, &mut SearchTrace::default());
+        let mut overlay = CostOverlay::default();
+        assert_eq!(route(&overlay), 4);
+
+        overlay.execute(&graph, "block 3,1").unwrap();
+        assert_eq!(overlay.get(4), Some(CostUpdate::Block));
+        assert_eq!(route(&overlay), 6);
+
+        overlay.execute(&graph, "multiply 0 3").unwrap();
+        overlay.execute(&graph, "restore 3,1").unwrap();
+        assert_eq!(overlay.apply(0, 5), Some(15));
+        assert_eq!(overlay.len(), 1);
+        assert_eq!(route(&overlay), 4);
+
+        // Huge factors saturate instead of overflowing the distances
+        overlay.execute(&graph, "multiply 3,1 1e300").unwrap();
+        assert_eq!(overlay.apply(4, 1), Some(EdgeCost::MAX));
+        assert_eq!(route(&overlay), 12);
+
+        overlay.execute(&graph, "reset").unwrap();
+        assert!(overlay.is_empty());
+        // The graph itself never changes
+        assert_eq!(router::shortest_path(&graph, 0, 2), 4);
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let graph = fixture("detour");
+        let mut overlay = CostOverlay::default();
+        for command in ["block 5", "block 2,0", "block 9,1", "multiply 0 -1", "multiply 0 inf", "multiply 0 NaN", "multiply 0", "close 0", ""] {
+            assert!(overlay.execute(&graph, command).is_err(), "{}", command);
+        }
+        assert!(overlay.is_empty());
+        assert!(is_command("reset") && !is_command("3 5") && !is_command("-48.1,9.0 5"));
+    }
+}
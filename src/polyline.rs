@@ -0,0 +1,96 @@
+use crate::coords::Coords;
+
+/// Encodes coordinates with Google's encoded polyline algorithm. `precision` is the number of
+/// decimal places kept, 5 for Google Maps and 6 for OSRM and Valhalla.
+pub fn encode(coords: &[Coords], precision: u32) -> String {
+    let factor = 10f64.powi(precision as i32);
+    let mut result = String::new();
+    let mut last = (0i64, 0i64);
+
+    for coords in coords {
+        let lat = (coords.lat * factor).round() as i64;
+        let lon = (coords.lon * factor).round() as i64;
+        encode_value(lat - last.0, &mut result);
+        encode_value(lon - last.1, &mut result);
+        last = (lat, lon);
+    }
+
+    result
+}
+
+fn encode_value(value: i64, result: &mut String) {
+    let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+    while value >= 0x20 {
+        result.push(char::from((0x20 | (value & 0x1f)) as u8 + 63));
+        value >>= 5;
+    }
+    result.push(char::from(value as u8 + 63));
+}
+
+/// The inverse of `encode`
+pub fn decode(encoded: &str, precision: u32) -> Result<Vec<Coords>, String> {
+    let factor = 10f64.powi(precision as i32);
+    let mut bytes = encoded.bytes();
+    let mut result = Vec::new();
+    let mut last = (0i64, 0i64);
+
+    while bytes.len() > 0 {
+        last.0 += decode_value(&mut bytes)?;
+        last.1 += decode_value(&mut bytes).map_err(|_| "The polyline ends after a latitude".to_string())?;
+        result.push(Coords::deg(last.0 as f64 / factor, last.1 as f64 / factor));
+    }
+
+    Ok(result)
+}
+
+fn decode_value(bytes: &mut impl Iterator<Item = u8>) -> Result<i64, String> {
+    let mut value = 0i64;
+    let mut shift = 0;
+    loop {
+        let byte = bytes.next().ok_or("The polyline ends in the middle of a value")?;
+        if !(63..127).contains(&byte) || shift > 60 {
+            return Err(format!("Invalid polyline character '{}'", char::from(byte)));
+        }
+
+        let chunk = (byte - 63) as i64;
+        value |= (chunk & 0x1f) << shift;
+        shift += 5;
+        if chunk < 0x20 {
+            break;
+        }
+    }
+
+    Ok(if value & 1 == 1 { !(value >> 1) } else { value >> 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode};
+    use crate::coords::Coords;
+
+    #[test]
+    fn google_example() {
+        let coords = [Coords::deg(38.5, -120.2), Coords::deg(40.7, -120.95), Coords::deg(43.252, -126.453)];
+        assert_eq!(encode(&coords, 5), "_p~iF~ps|U_ulLnnqC_mqNvxq`@");
+        assert_eq!(decode("_p~iF~ps|U_ulLnnqC_mqNvxq`@", 5).unwrap(), coords);
+    }
+
+    #[test]
+    fn round_trip() {
+        let coords = [Coords::deg(48.745412, 9.106712), Coords::deg(-33.918861, 18.4233), Coords::deg(0.0, -0.000001)];
+        for precision in [5, 6] {
+            let decoded = decode(&encode(&coords, precision), precision).unwrap();
+            for (a, b) in coords.iter().zip(&decoded) {
+                assert!((a.lat - b.lat).abs() < 10f64.powi(-(precision as i32)));
+                assert!((a.lon - b.lon).abs() < 10f64.powi(-(precision as i32)));
+            }
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(decode("_p~iF", 5).is_err());
+        assert!(decode("_p~iF~ps|", 5).is_err());
+        assert!(decode("abc def", 5).is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs;
+
+use serde_json::Value;
+
+use crate::cost::{self, CostFunction};
+use crate::edge::EdgeCost;
+use crate::Graph;
+
+/// Road types which bicycles and pedestrians may not use: motorway, trunk and their links
+const MOTOR_ROADS: [u8; 4] = [1, 2, 6, 7];
+
+/// A named way of travelling. It decides which road types may be used and minimises the travel
+/// time in milliseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub name: String,
+    /// Whether each road type may be used, indexed by road type
+    allowed: Vec<bool>,
+    /// A fixed speed in km/h which ignores the speeds of the roads, e.g. for walking
+    pub speed: Option<f64>,
+    /// The fastest speed in km/h the vehicle can go
+    pub max_speed: Option<f64>,
+}
+
+impl Profile {
+    /// A profile which may use all road types at their speed
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), allowed: vec![true; 256], speed: None, max_speed: None }
+    }
+
+    pub fn car() -> Self {
+        Self::new("car")
+    }
+
+    pub fn bicycle() -> Self {
+        Self { speed: Some(18.0), ..Self::new("bicycle").forbid(&MOTOR_ROADS) }
+    }
+
+    pub fn foot() -> Self {
+        Self { speed: Some(5.0), ..Self::new("foot").forbid(&MOTOR_ROADS) }
+    }
+
+    pub fn forbid(mut self, road_types: &[u8]) -> Self {
+        road_types.iter().for_each(|road_type| self.allowed[*road_type as usize] = false);
+        self
+    }
+
+    /// Only allows the given road types. Edges of unknown type (0) are then forbidden, too.
+    pub fn allow_only(mut self, road_types: &[u8]) -> Self {
+        self.allowed = vec![false; 256];
+        road_types.iter().for_each(|road_type| self.allowed[*road_type as usize] = true);
+        self
+    }
+
+    pub fn is_allowed(&self, road_type: u8) -> bool {
+        self.allowed[road_type as usize]
+    }
+
+    /// The speed in km/h on an edge
+    pub fn speed_on(&self, graph: &Graph, edge: usize) -> f64 {
+        let speed = self.speed.unwrap_or_else(|| cost::speed(graph.edge_attributes(edge)));
+        self.max_speed.map_or(speed, |max_speed| speed.min(max_speed))
+    }
+
+    /// Parses the settings of a profile from the config file, see `Profiles::from_json`
+    fn from_json(name: &str, value: &Value) -> Result<Profile, String> {
+        let object = value.as_object().ok_or_else(|| format!("The profile '{}' must be an object", name))?;
+        let mut profile = Profile::new(name);
+        for (key, value) in object {
+            match key.as_str() {
+                "allowed" => profile = profile.allow_only(&road_types(name, value)?),
+                "forbidden" => profile = profile.forbid(&road_types(name, value)?),
+                "speed" => profile.speed = Some(positive(name, key, value)?),
+                "max_speed" => profile.max_speed = Some(positive(name, key, value)?),
+                _ => return Err(format!("Unknown setting '{}' of the profile '{}'", key, name)),
+            }
+        }
+        Ok(profile)
+    }
+}
+
+impl CostFunction for Profile {
+    fn cost(&self, graph: &Graph, edge: usize) -> EdgeCost {
+        (cost::length(graph, edge) / (self.speed_on(graph, edge) / 3.6) * 1000.0).round() as EdgeCost
+    }
+
+    fn allows(&self, graph: &Graph, edge: usize) -> bool {
+        self.is_allowed(graph.edge_attributes(edge).road_type)
+    }
+}
+
+fn road_types(name: &str, value: &Value) -> Result<Vec<u8>, String> {
+    let invalid = || format!("The road types of the profile '{}' must be a list of numbers from 0 to 255", name);
+    value.as_array().ok_or_else(invalid)?.iter()
+        .map(|road_type| road_type.as_u64().filter(|road_type| *road_type <= u8::MAX as u64).map(|road_type| road_type as u8).ok_or_else(invalid))
+        .collect()
+}
+
+fn positive(name: &str, key: &str, value: &Value) -> Result<f64, String> {
+    value.as_f64().filter(|value| *value > 0.0)
+        .ok_or_else(|| format!("The '{}' of the profile '{}' must be a positive number", key, name))
+}
+
+/// The profiles which can be chosen by name
+#[derive(Clone, Debug)]
+pub struct Profiles {
+    profiles: Vec<Profile>,
+}
+
+impl Default for Profiles {
+    /// The built-in profiles 'car', 'bicycle' and 'foot'
+    fn default() -> Self {
+        Self { profiles: vec![Profile::car(), Profile::bicycle(), Profile::foot()] }
+    }
+}
+
+impl Profiles {
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// The profile with the given name, or `default` without a name
+    pub fn cost_function<'a>(&'a self, name: Option<&str>, default: &'a dyn CostFunction) -> Result<&'a dyn CostFunction, String> {
+        let Some(name) = name else { return Ok(default) };
+        match self.get(name) {
+            Some(profile) => Ok(profile),
+            None => Err(format!("unknown profile '{}', expected one of {}", name, self.names().join(", "))),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.profiles.iter().map(|profile| profile.name.as_str()).collect()
+    }
+
+    /// Adds a profile, replacing the one with the same name
+    pub fn insert(&mut self, profile: Profile) {
+        match self.profiles.iter_mut().find(|existing| existing.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    /// The built-in profiles together with the ones of a config file like
+    /// `{"truck": {"forbidden": [13], "max_speed": 80}, "foot": {"speed": 4}}`. Every profile may set
+    /// the road types it may use ('allowed') or may not use ('forbidden'), a fixed 'speed' and a 'max_speed'.
+    pub fn from_json(value: &Value) -> Result<Profiles, String> {
+        let object = value.as_object().ok_or("The profile config must be an object of profiles")?;
+        let mut profiles = Profiles::default();
+        for (name, value) in object {
+            profiles.insert(Profile::from_json(name, value)?);
+        }
+        Ok(profiles)
+    }
+
+    pub fn read_file(path: &str) -> Result<Profiles, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Self::from_json(&serde_json::from_str(&text).map_err(|err| err.to_string())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Profile, Profiles};
+    use crate::coords::Coords;
+    use crate::edge::{Edge, EdgeAttributes};
+    use crate::router::{self, SearchTrace};
+    use crate::Graph;
+
+    #[test]
+    fn config() {
+        let profiles = Profiles::from_json(&json!({"truck": {"forbidden": [13], "max_speed": 80}, "foot": {"speed": 4}})).unwrap();
+        assert_eq!(profiles.names(), vec!["car", "bicycle", "foot", "truck"]);
+        let truck = profiles.get("truck").unwrap();
+        assert!(!truck.is_allowed(13) && truck.is_allowed(1));
+        assert_eq!(truck.max_speed, Some(80.0));
+        assert_eq!(profiles.get("foot").unwrap().speed, Some(4.0));
+
+        let bus = Profiles::from_json(&json!({"bus": {"allowed": [1, 2]}})).unwrap();
+        assert!(!bus.get("bus").unwrap().is_allowed(0));
+        assert!(Profiles::from_json(&json!({"car": {"speed": -1}})).is_err());
+        assert!(Profiles::from_json(&json!({"car": {"forbidden": [300]}})).is_err());
+        assert!(Profiles::from_json(&json!({"car": {"wheels": 4}})).is_err());
+    }
+
+    #[test]
+    fn forbidden_roads() {
+        // A motorway from 0 to 2 and a longer detour over 1
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(48.01, 9.01), Coords::deg(48.0, 9.02)];
+        let edges = vec![Edge::new(0, 2, 1), Edge::new(0, 1, 1), Edge::new(1, 2, 1)];
+        let motorway = EdgeAttributes { road_type: 1, max_speed: 120 };
+        let residential = EdgeAttributes { road_type: 12, max_speed: 0 };
+        let graph = Graph::from_edges_with_attributes(coords, edges, vec![motorway, residential, residential]);
+
+        let route = |profile: &Profile| {
+            let mut trace = SearchTrace::default().with_pred();
+            let cost = router::shortest_path_with(&graph, profile, 0, 2, &mut trace);
+            (cost, trace.path_to(&graph, 0, 2).nodes())
+        };
+        assert_eq!(route(&Profile::car()).1, vec![0, 2]);
+        let (bicycle, nodes) = route(&Profile::bicycle());
+        assert_eq!(nodes, vec![0, 1, 2]);
+        let (foot, _) = route(&Profile::foot());
+        assert!(foot > bicycle * 3);
+        assert_eq!(route(&Profile::foot().allow_only(&[1])).1, vec![0, 2]);
+        assert_eq!(router::shortest_path_with(&graph, &Profile::car().allow_only(&[13]), 0, 2, &mut SearchTrace::default()), -1);
+    }
+}
@@ -0,0 +1,292 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::edge::EdgeCost;
+
+/// A min-priority queue of nodes as used by dijkstra. Queues without decrease-key keep the old
+/// entries of a node around, so a popped entry may be stale and has to be checked against the distance.
+pub trait PriorityQueue {
+    /// An empty queue for a graph with `node_count` nodes
+    fn new(node_count: usize) -> Self;
+    /// Inserts the node, or lowers its cost if it is already queued and the queue supports decrease-key.
+    /// Returns false if an existing entry was lowered instead of adding one.
+    fn push(&mut self, node: usize, cost: EdgeCost) -> bool;
+    /// Removes the node with the lowest cost
+    fn pop(&mut self) -> Option<(usize, EdgeCost)>;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The queues to choose from on the command line
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueueKind {
+    Binary,
+    Radix,
+    Dary,
+}
+
+impl Display for QueueKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueKind::Binary => write!(f, "binary"),
+            QueueKind::Radix => write!(f, "radix"),
+            QueueKind::Dary => write!(f, "dary"),
+        }
+    }
+}
+
+impl FromStr for QueueKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "binary" => Ok(QueueKind::Binary),
+            "radix" => Ok(QueueKind::Radix),
+            "dary" | "d-ary" => Ok(QueueKind::Dary),
+            _ => Err(format!("Unknown priority queue '{}', expected 'binary', 'radix' or 'dary'", s)),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct Entry {
+    cost: EdgeCost,
+    node: usize,
+}
+
+// Flipped, so the standard max-heap pops the lowest cost first. Ties are broken by the node to keep
+// `Ord` consistent with `PartialEq`.
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.cmp(&self.cost).then_with(|| self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `std::collections::BinaryHeap` with lazy deletion
+pub struct BinaryQueue {
+    heap: BinaryHeap<Entry>,
+}
+
+impl PriorityQueue for BinaryQueue {
+    fn new(_node_count: usize) -> Self {
+        Self { heap: BinaryHeap::new() }
+    }
+
+    fn push(&mut self, node: usize, cost: EdgeCost) -> bool {
+        self.heap.push(Entry { cost, node });
+        true
+    }
+
+    fn pop(&mut self) -> Option<(usize, EdgeCost)> {
+        self.heap.pop().map(|entry| (entry.node, entry.cost))
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+/// A monotone radix heap with lazy deletion. Keys must never be smaller than the last popped key,
+/// which holds for dijkstra with non-negative costs. Pushing a smaller key panics. Entries are kept
+/// in buckets by the highest bit in which they differ from the last popped key, so each entry is
+/// moved at most 64 times.
+pub struct RadixHeap {
+    buckets: Vec<Vec<(EdgeCost, usize)>>,
+    last: EdgeCost,
+    len: usize,
+}
+
+impl RadixHeap {
+    fn bucket(&self, cost: EdgeCost) -> usize {
+        let bits = (cost ^ self.last) as u64;
+        64 - bits.leading_zeros() as usize
+    }
+}
+
+impl PriorityQueue for RadixHeap {
+    fn new(_node_count: usize) -> Self {
+        Self { buckets: vec![Vec::new(); 65], last: 0, len: 0 }
+    }
+
+    fn push(&mut self, node: usize, cost: EdgeCost) -> bool {
+        // A smaller key would land in a wrong bucket and be popped out of order
+        assert!(cost >= self.last, "Radix heaps only accept keys at least as large as the last popped one");
+        let bucket = self.bucket(cost);
+        self.buckets[bucket].push((cost, node));
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<(usize, EdgeCost)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        if self.buckets[0].is_empty() {
+            // Everything in the first non-empty bucket moves to lower buckets relative to its minimum
+            let index = self.buckets.iter().position(|bucket| !bucket.is_empty()).unwrap();
+            let entries = std::mem::take(&mut self.buckets[index]);
+            self.last = entries.iter().map(|(cost, _)| *cost).min().unwrap();
+            for (cost, node) in entries {
+                let bucket = self.bucket(cost);
+                self.buckets[bucket].push((cost, node));
+            }
+        }
+
+        self.len -= 1;
+        self.buckets[0].pop().map(|(cost, node)| (node, cost))
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// An addressable d-ary heap which supports decrease-key, so no stale entries are popped
+pub struct DaryHeap<const D: usize> {
+    heap: Vec<(EdgeCost, usize)>,
+    /// The index in `heap` of every node, `usize::MAX` if it isn't queued
+    position: Vec<usize>,
+}
+
+/// The arity which usually performs best for dijkstra on road networks
+pub type QuaternaryHeap = DaryHeap<4>;
+
+impl<const D: usize> DaryHeap<D> {
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.position[self.heap[a].1] = a;
+        self.position[self.heap[b].1] = b;
+    }
+
+    fn sift_up(&mut self, mut index: usize) {
+        while index > 0 {
+            let parent = (index - 1) / D;
+            if self.heap[parent].0 <= self.heap[index].0 {
+                break;
+            }
+            self.swap(parent, index);
+            index = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        loop {
+            let first = index * D + 1;
+            let Some(smallest) = (first..(first + D).min(self.heap.len())).min_by_key(|child| self.heap[*child].0) else { break };
+            if self.heap[smallest].0 >= self.heap[index].0 {
+                break;
+            }
+            self.swap(smallest, index);
+            index = smallest;
+        }
+    }
+}
+
+impl<const D: usize> PriorityQueue for DaryHeap<D> {
+    fn new(node_count: usize) -> Self {
+        Self { heap: Vec::new(), position: vec![usize::MAX; node_count] }
+    }
+
+    fn push(&mut self, node: usize, cost: EdgeCost) -> bool {
+        let index = self.position[node];
+        if index == usize::MAX {
+            self.heap.push((cost, node));
+            self.position[node] = self.heap.len() - 1;
+            self.sift_up(self.heap.len() - 1);
+            return true;
+        }
+        if cost < self.heap[index].0 {
+            self.heap[index].0 = cost;
+            self.sift_up(index);
+        }
+        false
+    }
+
+    fn pop(&mut self) -> Option<(usize, EdgeCost)> {
+        if self.heap.is_empty() {
+            return None;
+        }
+
+        let last = self.heap.len() - 1;
+        self.swap(0, last);
+        let (cost, node) = self.heap.pop().unwrap();
+        self.position[node] = usize::MAX;
+        if !self.heap.is_empty() {
+            self.sift_down(0);
+        }
+        Some((node, cost))
+    }
+
+    fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryQueue, PriorityQueue, QuaternaryHeap, RadixHeap};
+    use crate::rng::Rng;
+
+    /// Checks that costs are popped in order. Keys are never below the last popped one, like in dijkstra.
+    fn check<Q: PriorityQueue>() {
+        let mut rng = Rng::new(5);
+        let mut queue = Q::new(1000);
+        let mut popped = Vec::new();
+        let mut last = 0;
+        for node in 0..1000 {
+            queue.push(node, last + rng.below(100) as i64);
+            if node % 3 == 0 {
+                let (_, cost) = queue.pop().unwrap();
+                popped.push(cost);
+                last = cost;
+            }
+        }
+        while let Some((_, cost)) = queue.pop() {
+            popped.push(cost);
+        }
+
+        assert_eq!(popped.len(), 1000);
+        assert!(popped.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn order() {
+        check::<BinaryQueue>();
+        check::<RadixHeap>();
+        check::<QuaternaryHeap>();
+    }
+
+    #[test]
+    fn decrease_key() {
+        let mut heap = QuaternaryHeap::new(3);
+        assert!(heap.push(0, 10));
+        assert!(heap.push(1, 5));
+        assert!(!heap.push(0, 2));
+        assert!(!heap.push(1, 7));
+        assert_eq!(heap.len(), 2);
+        assert_eq!(heap.pop(), Some((0, 2)));
+        assert_eq!(heap.pop(), Some((1, 5)));
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn radix_monotone() {
+        let mut heap = RadixHeap::new(2);
+        heap.push(0, 5);
+        heap.pop();
+        heap.push(1, 4);
+    }
+}
@@ -0,0 +1,54 @@
+/// A small deterministic random number generator (SplitMix64). Not suitable for cryptography, but
+/// good enough to generate reproducible queries and test graphs without any external crate.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, `bound` must not be zero
+    pub fn below(&mut self, bound: u64) -> u64 {
+        // Multiply-shift is slightly biased for huge bounds, which doesn't matter here
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    /// A number in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn reproducible() {
+        let a = (0..10).map({ let mut rng = Rng::new(7); move |_| rng.next_u64() }).collect::<Vec<_>>();
+        let b = (0..10).map({ let mut rng = Rng::new(7); move |_| rng.next_u64() }).collect::<Vec<_>>();
+        assert_eq!(a, b);
+        assert_ne!(a, (0..10).map({ let mut rng = Rng::new(8); move |_| rng.next_u64() }).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bounds() {
+        let mut rng = Rng::new(1);
+        for _ in 0..1000 {
+            assert!(rng.below(17) < 17);
+            let value = rng.next_f64();
+            assert!((0.0..1.0).contains(&value));
+        }
+    }
+}
@@ -1,50 +1,189 @@
-use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::Range;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 use owo_colors::OwoColorize;
 use pbr::ProgressBar;
 
-use crate::edge::{EdgeCost};
+use crate::avoid::Avoid;
+use crate::coords::Coords;
+use crate::cost::{CostFunction, Metric, StoredCost};
+use crate::edge::{Edge, EdgeCost, EdgePath};
+use crate::graph::IncomingEdges;
+use crate::grid::SnapTree;
+use crate::overlay::{CostOverlay, Overlaid};
+use crate::profile::Profiles;
+use crate::queue::{BinaryQueue, PriorityQueue, QuaternaryHeap, QueueKind, RadixHeap};
 use crate::Graph;
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-struct State {
-    cost: EdgeCost,
-    position: usize,
+/// A point of a query, either a node id or a coordinate which is snapped to the nearest node
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Location {
+    Node(usize),
+    Coords(Coords),
 }
 
-// The priority queue depends on `Ord`.
-// Explicitly implement the trait so the queue becomes a min-heap
-// instead of a max-heap.
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // Notice that the we flip the ordering on costs.
-        // In case of a tie we compare positions - this step is necessary
-        // to make implementations of `PartialEq` and `Ord` consistent.
-        other.cost.cmp(&self.cost)
-            .then_with(|| self.position.cmp(&other.position))
+/// Parses either `<node id>` or `<lat>,<lon>`
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(',') {
+            Some((lat, lon)) => match (lat.trim().parse::<f64>(), lon.trim().parse::<f64>()) {
+                (Ok(lat), Ok(lon)) => Ok(Location::Coords(Coords::deg(lat, lon))),
+                _ => Err(format!("Expected '<lat>,<lon>' but got '{}'", s)),
+            },
+            None => s.trim().parse::<usize>()
+                .map(Location::Node)
+                .map_err(|_| format!("Expected a node id or '<lat>,<lon>' but got '{}'", s)),
+        }
+    }
+}
+
+/// The algorithms which answer point-to-point queries
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Algorithm {
+    Dijkstra,
+}
+
+impl Algorithm {
+    pub fn shortest_path(&self, graph: &Graph, costs: &dyn CostFunction, start: usize, goal: usize, queue: QueueKind) -> EdgeCost {
+        match self {
+            Algorithm::Dijkstra => shortest_path_queue(graph, costs, start, goal, queue),
+        }
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Algorithm::Dijkstra => write!(f, "dijkstra"),
+        }
     }
 }
 
-// `PartialOrd` needs to be implemented as well.
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dijkstra" => Ok(Algorithm::Dijkstra),
+            _ => Err(format!("Unknown algorithm '{}', expected 'dijkstra'", s)),
+        }
+    }
+}
+
+/// What `solve_file` records besides the distances
+#[derive(Clone, Default)]
+pub struct SolveOptions {
+    /// The nodes coordinates are snapped to
+    pub snap: SnapTree,
+    pub track_paths: bool,
+    pub stats: bool,
+    /// The cost function of queries without a profile
+    pub metric: Metric,
+    /// The profiles queries can choose by name
+    pub profiles: Arc<Profiles>,
+    /// Cost updates applied on top of the metric or profile of every query
+    pub overlay: Arc<CostOverlay>,
+    /// The nodes routes must not pass, empty if no area is avoided
+    pub blocked: Arc<Vec<bool>>,
+}
+
+/// The answer to a single query of a query file
+#[derive(Default)]
+pub struct QueryResult {
+    /// The distance, -1 if the target is unreachable
+    pub cost: EdgeCost,
+    /// The route, only present if paths were tracked and the target is reachable
+    pub path: Option<EdgePath>,
+    /// The search space, only present if stats were requested
+    pub stats: Option<SearchStats>,
+    /// The cost of every leg if the query has via points and all of them are reachable
+    pub legs: Vec<EdgeCost>,
+    /// Why the query couldn't be answered, e.g. a malformed line or an unknown profile. The cost is -1 then.
+    pub error: Option<String>,
+}
+
+impl QueryResult {
+    fn error(error: String) -> Self {
+        Self { cost: -1, error: Some(error), ..Default::default() }
     }
 }
 
-pub fn solve_file(graph: Arc<Graph>, thread_count: u32, path: String) {
+/// The cost followed by the cost of every leg, or the error
+impl Display for QueryResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(error) = &self.error {
+            return write!(f, "error: {}", error);
+        }
+        write!(f, "{}", self.cost)?;
+        if !self.legs.is_empty() {
+            let legs = self.legs.iter().map(|leg| leg.to_string()).collect::<Vec<_>>();
+            write!(f, " = {}", legs.join(" + "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Answers a query line `<waypoint> <waypoint> ... [profile]`. Coordinates are snapped to the nearest
+/// node passing the snap filter, queries without a profile use the metric of the options. Invalid
+/// lines are answered with an error instead of a cost.
+pub fn answer_query(graph: &Graph, line: &str, options: &SolveOptions) -> QueryResult {
+    let Some((locations, profile)) = parse_via_query(line) else {
+        return QueryResult::error("expected '<waypoint> <waypoint> ... [profile]'".to_string());
+    };
+    let mut waypoints = Vec::new();
+    for location in locations {
+        match location {
+            Location::Node(node) if node < graph.node_count() => waypoints.push(node),
+            Location::Node(node) => return QueryResult::error(format!("{} is not a node of the graph", node)),
+            Location::Coords(coords) => match options.snap.get(graph) {
+                tree if !tree.is_empty() => waypoints.push(tree.nearest_nodes(graph.nodes(), coords, 1)[0]),
+                _ => return QueryResult::error("there is no node to snap coordinates to".to_string()),
+            },
+        }
+    }
+    let costs = match options.profiles.cost_function(profile, &options.metric) {
+        Ok(costs) => costs,
+        Err(err) => return QueryResult::error(err),
+    };
+    let overlaid = Overlaid::new(costs, &options.overlay);
+    let costs: &dyn CostFunction = if options.overlay.is_empty() { costs } else { &overlaid };
+    let avoid = Avoid::new(costs, &options.blocked);
+    let costs: &dyn CostFunction = if options.blocked.is_empty() { costs } else { &avoid };
+
+    let mut trace = SearchTrace::default();
+    if options.track_paths {
+        trace = trace.with_pred();
+    }
+    if options.stats {
+        trace = trace.with_stats();
+    }
+    match via_route(graph, costs, &waypoints, &mut trace) {
+        Ok(route) => QueryResult {
+            cost: route.cost(),
+            path: route.path(),
+            stats: trace.stats,
+            legs: if route.legs.len() > 1 { route.legs.iter().map(|leg| leg.cost).collect() } else { Vec::new() },
+            error: None,
+        },
+        Err(_) => QueryResult { cost: -1, stats: trace.stats, ..Default::default() },
+    }
+}
+
+pub fn solve_file(graph: Arc<Graph>, thread_count: u32, path: String, options: SolveOptions) -> Vec<QueryResult> {
     let file = File::open(path).expect("Couldn't open query file. Please check if the path is correct!");
     let reader = BufReader::new(file);
     let mut handles = Vec::new();
-    let lines = reader.lines().into_iter().map(|x| x.unwrap()).collect::<Vec<String>>();
+    let lines = reader.lines().map(|x| x.unwrap()).collect::<Vec<String>>();
     let line_count = lines.len();
     // Fill the distance array with values
-    let distances = (0..line_count).map(|_| -1).collect::<Vec<i64>>();
+    let distances = (0..line_count).map(|_| QueryResult { cost: -1, ..Default::default() }).collect::<Vec<QueryResult>>();
     let distances = Arc::new(Mutex::new(distances));
     let lines_iter = Arc::new(Mutex::new((lines.into_iter(), 0)));
     println!("{}", format!("Calculating distances multi-threaded with {} threads...", thread_count).yellow());
@@ -53,6 +192,7 @@ pub fn solve_file(graph: Arc<Graph>, thread_count: u32, path: String) {
         let graph = graph.clone();
         let distances = distances.clone();
         let lines_iter = lines_iter.clone();    
+        let options = options.clone();
         let handle = thread::spawn(move || {
             loop {
                 let mut guard = lines_iter.lock().unwrap();
@@ -61,11 +201,8 @@ pub fn solve_file(graph: Arc<Graph>, thread_count: u32, path: String) {
                 if let Some(line) = guard.0.next() {
                     // Unlock the mutex
                     drop(guard);
-                    let mut split = line.split(char::is_whitespace);
-                    let start = split.next().unwrap().parse::<usize>().unwrap();
-                    let goal = split.next().unwrap().parse::<usize>().unwrap();
-                    let distance: i64 = shortest_path(&graph, start, goal);
-                    distances.lock().unwrap()[index] = distance;
+                    let result = answer_query(&graph, &line, &options);
+                    distances.lock().unwrap()[index] = result;
                 } else {
                     break;
                 }
@@ -100,45 +237,607 @@ pub fn solve_file(graph: Arc<Graph>, thread_count: u32, path: String) {
     
     // Write out the distances
     println!("\n\n");
-    distances.lock().unwrap().iter().for_each(|dist| {
-        println!("{}", *dist);
+    let distances = std::mem::take(&mut *distances.lock().unwrap());
+    let mut total = SearchStats::default();
+    distances.iter().for_each(|result| match &result.stats {
+        Some(stats) => {
+            println!("{} ({})", result, stats);
+            total.add(stats);
+        }
+        None => println!("{}", result),
     });
+
+    if options.stats && line_count > 0 {
+        println!("\nTotal: {}", total);
+        println!("Mean:  {}", total.mean(line_count));
+    }
+    distances
+}
+
+/// Parses a query line of the form `<source node id> <target node id>`
+pub fn parse_query(line: &str) -> Option<(usize, usize)> {
+    let mut split = line.split_whitespace();
+    let start = split.next()?.parse::<usize>().ok()?;
+    let goal = split.next()?.parse::<usize>().ok()?;
+    Some((start, goal))
+}
+
+/// Parses a query line of the form `<waypoint> <waypoint> ... [profile]` with at least two waypoints,
+/// each either a node id or `<lat>,<lon>`. Only the last word may be a profile.
+pub fn parse_via_query(line: &str) -> Option<(Vec<Location>, Option<&str>)> {
+    let mut words = line.split_whitespace().collect::<Vec<_>>();
+    let profile = words.last().filter(|word| word.parse::<Location>().is_err()).copied();
+    if profile.is_some() {
+        words.pop();
+    }
+    let waypoints = words.iter().map(|word| word.parse::<Location>().ok()).collect::<Option<Vec<_>>>()?;
+    (waypoints.len() >= 2).then_some((waypoints, profile))
+}
+
+/// Counters describing the search space of a single run
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct SearchStats {
+    pub settled_nodes: u64,
+    /// Edges looked at while settling nodes
+    pub relaxed_edges: u64,
+    pub heap_pushes: u64,
+    /// Queued nodes whose cost was lowered in place, only queues with decrease-key do this
+    pub decrease_keys: u64,
+    pub heap_pops: u64,
+    /// Popped entries which were outdated because the node was reached cheaper in the meantime.
+    /// This is what the lazy deletion of the binary heap costs compared to a decrease-key.
+    pub stale_skipped: u64,
+    pub peak_heap_size: u64,
+}
+
+impl SearchStats {
+    /// Sums the counters, the peak heap size is the maximum of both
+    pub fn add(&mut self, other: &SearchStats) {
+        self.settled_nodes += other.settled_nodes;
+        self.relaxed_edges += other.relaxed_edges;
+        self.heap_pushes += other.heap_pushes;
+        self.decrease_keys += other.decrease_keys;
+        self.heap_pops += other.heap_pops;
+        self.stale_skipped += other.stale_skipped;
+        self.peak_heap_size = self.peak_heap_size.max(other.peak_heap_size);
+    }
+
+    /// Formats the counters divided by `count`, except for the peak heap size
+    pub fn mean(&self, count: usize) -> String {
+        let mean = |value: u64| value as f64 / count.max(1) as f64;
+        format!(
+            "settled: {:.1}, relaxed: {:.1}, pushes: {:.1}, decrease-keys: {:.1}, pops: {:.1}, stale: {:.1}, peak heap: {}",
+            mean(self.settled_nodes), mean(self.relaxed_edges), mean(self.heap_pushes), mean(self.decrease_keys),
+            mean(self.heap_pops), mean(self.stale_skipped), self.peak_heap_size
+        )
+    }
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "settled: {}, relaxed: {}, pushes: {}, decrease-keys: {}, pops: {}, stale: {}, peak heap: {}",
+            self.settled_nodes, self.relaxed_edges, self.heap_pushes, self.decrease_keys, self.heap_pops, self.stale_skipped,
+            self.peak_heap_size
+        )
+    }
+}
+
+/// Optional bookkeeping of a dijkstra run. Only the parts which are `Some` are recorded.
+#[derive(Default)]
+pub struct SearchTrace {
+    /// For every reached node the index of the edge it was last relaxed over, `usize::MAX` otherwise
+    pub pred: Option<Vec<usize>>,
+    /// The nodes in the order they were settled
+    pub settled: Option<Vec<usize>>,
+    pub stats: Option<SearchStats>,
+}
+
+impl SearchTrace {
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(SearchStats::default());
+        self
+    }
+
+    pub fn with_pred(mut self) -> Self {
+        self.pred = Some(Vec::new());
+        self
+    }
+
+    pub fn with_settled(mut self) -> Self {
+        self.settled = Some(Vec::new());
+        self
+    }
+
+    /// The edges from `start` to `goal`, requires the predecessors of a run in which `goal` was reached
+    pub fn path_to(&self, graph: &Graph, start: usize, goal: usize) -> EdgePath {
+        let pred = self.pred.as_ref().expect("The predecessors were not recorded");
+        let mut path = Vec::new();
+        let mut position = goal;
+        while position != start {
+            let edge = graph.edge(pred[position]).expect("The goal was not reached");
+            position = edge.src as usize;
+            path.push(edge.clone());
+        }
+        path.reverse();
+
+        EdgePath { path }
+    }
+}
+
+/// Which way a search follows the edges. Backward searches start at a target and keep the edge
+/// indices of the graph, so cost functions see the same edges in both directions.
+#[derive(Clone, Copy)]
+pub enum Direction<'a> {
+    Forward,
+    Backward(&'a IncomingEdges),
+}
+
+impl Direction<'_> {
+    /// The positions of the edges followed from `node`, see `edge_index`
+    pub fn edge_range(&self, graph: &Graph, node: usize) -> Range<usize> {
+        match self {
+            Direction::Forward => graph.outgoing_edge_range(node),
+            Direction::Backward(incoming) => incoming.range(node),
+        }
+    }
+
+    pub fn edge_index(&self, position: usize) -> usize {
+        match self {
+            Direction::Forward => position,
+            Direction::Backward(incoming) => incoming.edge_index(position),
+        }
+    }
+
+    /// The node the search reaches over `edge`
+    pub fn next(&self, edge: &Edge) -> usize {
+        match self {
+            Direction::Forward => edge.trg as usize,
+            Direction::Backward(_) => edge.src as usize,
+        }
+    }
+}
+
+/// The shortest paths from one source to every reachable node, or from every node which can reach
+/// it to a target
+pub struct ShortestPathTree {
+    /// The source, or the target of a reverse tree
+    pub source: usize,
+    /// Whether the paths lead to `source` instead of from it
+    pub reverse: bool,
+    /// The distance of every node, `EdgeCost::MAX` if it is unreachable
+    pub dist: Vec<EdgeCost>,
+    /// The index of the edge every node is reached over, or left over towards the target of a reverse
+    /// tree. `usize::MAX` for the source and unreachable nodes.
+    pub parent_edges: Vec<usize>,
+}
+
+impl ShortestPathTree {
+    pub fn is_reachable(&self, node: usize) -> bool {
+        self.dist.get(node).map_or(false, |dist| *dist != EdgeCost::MAX)
+    }
+
+    /// The node before `node` on its shortest path, or after it in a reverse tree. `None` for the
+    /// source and unreachable nodes.
+    pub fn parent(&self, graph: &Graph, node: usize) -> Option<usize> {
+        let edge = graph.edge(*self.parent_edges.get(node)?)?;
+        Some(if self.reverse { edge.trg } else { edge.src } as usize)
+    }
+
+    /// The nodes from `node` back to the source, both included. Empty if `node` is unreachable.
+    pub fn walk_to_root<'a>(&'a self, graph: &'a Graph, node: usize) -> impl Iterator<Item = usize> + 'a {
+        let first = self.is_reachable(node).then_some(node);
+        std::iter::successors(first, move |node| self.parent(graph, *node))
+    }
+
+    /// The edges from the source to `node`, or from `node` to the target of a reverse tree, in
+    /// O(path length). `None` if it is unreachable.
+    pub fn path_to(&self, graph: &Graph, node: usize) -> Option<EdgePath> {
+        if !self.is_reachable(node) {
+            return None;
+        }
+        let mut path = self.walk_to_root(graph, node)
+            .filter_map(|node| graph.edge(self.parent_edges[node]).cloned())
+            .collect::<Vec<_>>();
+        if !self.reverse {
+            path.reverse();
+        }
+        Some(EdgePath { path })
+    }
+
+    /// The nodes whose shortest paths lead over `node`, including `node` itself
+    pub fn subtree(&self, graph: &Graph, node: usize) -> Vec<usize> {
+        let mut children = vec![Vec::new(); self.dist.len()];
+        for child in 0..self.dist.len() {
+            if let Some(parent) = self.parent(graph, child) {
+                children[parent].push(child);
+            }
+        }
+
+        let mut subtree = Vec::new();
+        let mut stack = if self.is_reachable(node) { vec![node] } else { Vec::new() };
+        while let Some(node) = stack.pop() {
+            subtree.push(node);
+            stack.extend(&children[node]);
+        }
+        subtree
+    }
+}
+
+/// The complete shortest-path tree of a one-to-all dijkstra from `start`
+pub fn shortest_path_tree(graph: &Graph, start: usize) -> ShortestPathTree {
+    shortest_path_tree_with(graph, &StoredCost, start, Direction::Forward)
+}
+
+/// The complete shortest-path tree of a dijkstra from `root` weighed by `costs`. Searching backwards
+/// gives the reverse tree of the paths from all nodes to `root`.
+pub fn shortest_path_tree_with(graph: &Graph, costs: &dyn CostFunction, root: usize, direction: Direction) -> ShortestPathTree {
+    let mut trace = SearchTrace::default().with_pred();
+    let dist = dijkstra_until(graph, costs, root, direction, |_| false, &mut trace).1;
+    let reverse = matches!(direction, Direction::Backward(_));
+    ShortestPathTree { source: root, reverse, dist, parent_edges: trace.pred.unwrap() }
 }
 
 pub fn shortest_paths(graph: &Graph, start: usize) -> Vec<EdgeCost> {
-    dijkstra(graph, start, usize::MAX).1
+    dijkstra(graph, &StoredCost, start, usize::MAX, &mut SearchTrace::default()).1
 }
 
 pub fn shortest_path(graph: &Graph, start: usize, goal: usize) -> EdgeCost {
-    dijkstra(graph, start, goal).0
+    dijkstra(graph, &StoredCost, start, goal, &mut SearchTrace::default()).0
 }
 
-fn dijkstra(graph: &Graph, start: usize, goal: usize) -> (EdgeCost, Vec<EdgeCost>) {
-    let mut heap = BinaryHeap::with_capacity(graph.edge_count());
-    let mut dist = (0..graph.node_count()).map(|_| EdgeCost::MAX).collect::<Vec<EdgeCost>>();
+/// The distances from every node to `goal`, `EdgeCost::MAX` for nodes which can't reach it. Searches
+/// backwards over the incoming edges, which can be reused for any number of goals.
+pub fn shortest_paths_to(graph: &Graph, incoming: &IncomingEdges, goal: usize) -> Vec<EdgeCost> {
+    dijkstra_until(graph, &StoredCost, goal, Direction::Backward(incoming), |_| false, &mut SearchTrace::default()).1
+}
 
-    dist[start] = 0;
-    heap.push(State { cost: 0, position: start });
+/// Like `shortest_paths`, but records everything requested by `trace`
+pub fn shortest_paths_traced(graph: &Graph, start: usize, trace: &mut SearchTrace) -> Vec<EdgeCost> {
+    dijkstra(graph, &StoredCost, start, usize::MAX, trace).1
+}
 
-    while let Some(State { cost, position }) = heap.pop() {
-        if position == goal {
-            return (cost, dist);
+/// Like `shortest_path_with`, but runs dijkstra with the given priority queue
+pub fn shortest_path_queue(graph: &Graph, costs: &dyn CostFunction, start: usize, goal: usize, queue: QueueKind) -> EdgeCost {
+    let mut trace = SearchTrace::default();
+    let mut stats = SearchStats::default();
+    match queue {
+        QueueKind::Binary => dijkstra_counted::<BinaryQueue, _>(graph, costs, start, Direction::Forward, |node| node == goal, &mut trace, &mut stats).0,
+        QueueKind::Radix => dijkstra_counted::<RadixHeap, _>(graph, costs, start, Direction::Forward, |node| node == goal, &mut trace, &mut stats).0,
+        QueueKind::Dary => dijkstra_counted::<QuaternaryHeap, _>(graph, costs, start, Direction::Forward, |node| node == goal, &mut trace, &mut stats).0,
+    }
+}
+
+/// Like `shortest_path`, but records everything requested by `trace`
+pub fn shortest_path_traced(graph: &Graph, start: usize, goal: usize, trace: &mut SearchTrace) -> EdgeCost {
+    dijkstra(graph, &StoredCost, start, goal, trace).0
+}
+
+/// Like `shortest_path_traced`, but weighs the edges with `costs` instead of their stored cost
+pub fn shortest_path_with(graph: &Graph, costs: &dyn CostFunction, start: usize, goal: usize, trace: &mut SearchTrace) -> EdgeCost {
+    dijkstra(graph, costs, start, goal, trace).0
+}
+
+/// Like `shortest_paths_traced`, but weighs the edges with `costs` instead of their stored cost
+pub fn shortest_paths_with(graph: &Graph, costs: &dyn CostFunction, start: usize, trace: &mut SearchTrace) -> Vec<EdgeCost> {
+    dijkstra(graph, costs, start, usize::MAX, trace).1
+}
+
+/// The distances from `start` to each of `targets`, `EdgeCost::MAX` for targets it can't reach. The
+/// search stops as soon as all targets are settled.
+pub fn shortest_paths_among(graph: &Graph, costs: &dyn CostFunction, start: usize, targets: &[usize], trace: &mut SearchTrace) -> Vec<EdgeCost> {
+    let mut is_target = vec![false; graph.node_count()];
+    let mut remaining = 0;
+    for target in targets {
+        remaining += usize::from(!is_target[*target]);
+        is_target[*target] = true;
+    }
+    let dist = dijkstra_until(graph, costs, start, Direction::Forward, |node| {
+        remaining -= usize::from(is_target[node]);
+        remaining == 0
+    }, trace).1;
+    targets.iter().map(|target| dist[*target]).collect()
+}
+
+/// Finds the pair of start and goal candidates with the best summed rank that has a route. Every
+/// start candidate is tried with a single search which stops once all goal candidates are settled,
+/// so there are at most as many searches as start candidates. Returns the pair and the distance,
+/// `trace` holds the run of the returned pair.
+pub fn shortest_path_fallback(graph: &Graph, costs: &dyn CostFunction, starts: &[usize], goals: &[usize], trace: &mut SearchTrace) -> Option<(usize, usize, EdgeCost)> {
+    let mut best: Option<(usize, usize, usize, EdgeCost)> = None;
+    for (i, start) in starts.iter().enumerate() {
+        // Later start candidates can't beat the rank of the pair found, ties keep the earlier start
+        if best.map_or(false, |(rank_sum, ..)| i >= rank_sum) {
+            break;
+        }
+        let mut run = SearchTrace {
+            pred: trace.pred.as_ref().map(|_| Vec::new()),
+            settled: trace.settled.as_ref().map(|_| Vec::new()),
+            stats: trace.stats.map(|_| SearchStats::default()),
+        };
+        let dist = shortest_paths_among(graph, costs, *start, goals, &mut run);
+        let Some(j) = dist.iter().position(|dist| *dist != EdgeCost::MAX) else { continue };
+        if best.map_or(true, |(rank_sum, ..)| i + j < rank_sum) {
+            best = Some((i + j, *start, goals[j], dist[j]));
+            *trace = run;
         }
+    }
+    best.map(|(_, start, goal, cost)| (start, goal, cost))
+}
+
+/// One part of a route over via points
+pub struct Leg {
+    pub start: usize,
+    pub goal: usize,
+    pub cost: EdgeCost,
+    /// Only present if the predecessors were recorded
+    pub path: Option<EdgePath>,
+}
+
+/// A route over several waypoints, one leg between each two of them
+pub struct ViaRoute {
+    pub legs: Vec<Leg>,
+}
 
+impl ViaRoute {
+    pub fn cost(&self) -> EdgeCost {
+        self.legs.iter().map(|leg| leg.cost).sum()
+    }
+
+    /// The edges of all legs one after another, `None` if the predecessors were not recorded
+    pub fn path(&self) -> Option<EdgePath> {
+        let paths = self.legs.iter().map(|leg| leg.path.as_ref()).collect::<Option<Vec<_>>>()?;
+        Some(EdgePath { path: paths.iter().flat_map(|path| path.path.iter().cloned()).collect() })
+    }
+}
+
+/// Routes from the first waypoint over all others in order. `Err` holds the index of the first leg
+/// without a route. The legs only have paths if `trace` records predecessors, stats are summed over
+/// the legs.
+pub fn via_route(graph: &Graph, costs: &dyn CostFunction, waypoints: &[usize], trace: &mut SearchTrace) -> Result<ViaRoute, usize> {
+    let mut legs = Vec::new();
+    for (i, pair) in waypoints.windows(2).enumerate() {
+        let (start, goal) = (pair[0], pair[1]);
+        let mut leg_trace = SearchTrace::default();
+        if trace.pred.is_some() {
+            leg_trace = leg_trace.with_pred();
+        }
+        if trace.stats.is_some() {
+            leg_trace = leg_trace.with_stats();
+        }
+        let cost = shortest_path_with(graph, costs, start, goal, &mut leg_trace);
+        if let (Some(total), Some(stats)) = (trace.stats.as_mut(), &leg_trace.stats) {
+            total.add(stats);
+        }
+        if cost < 0 {
+            return Err(i);
+        }
+        let path = leg_trace.pred.is_some().then(|| leg_trace.path_to(graph, start, goal));
+        legs.push(Leg { start, goal, cost, path });
+    }
+    Ok(ViaRoute { legs })
+}
+
+/// Like `shortest_path`, but also returns the edges along the route. `None` if the goal is unreachable.
+pub fn shortest_route(graph: &Graph, start: usize, goal: usize) -> Option<(EdgeCost, EdgePath)> {
+    let mut trace = SearchTrace::default().with_pred();
+    let cost = shortest_path_traced(graph, start, goal, &mut trace);
+    if cost < 0 {
+        return None;
+    }
+
+    Some((cost, trace.path_to(graph, start, goal)))
+}
+
+/// Runs dijkstra from `start` until `goal` is settled
+fn dijkstra<C: CostFunction + ?Sized>(graph: &Graph, costs: &C, start: usize, goal: usize, trace: &mut SearchTrace) -> (EdgeCost, Vec<EdgeCost>) {
+    dijkstra_until(graph, costs, start, Direction::Forward, |node| node == goal, trace)
+}
+
+/// Runs dijkstra from `start` until `done` returns true for a settled node. Returns the distance of
+/// that node, -1 if the search ran out of nodes before, and the distances of all nodes.
+fn dijkstra_until<C: CostFunction + ?Sized>(graph: &Graph, costs: &C, start: usize, direction: Direction, done: impl FnMut(usize) -> bool, trace: &mut SearchTrace) -> (EdgeCost, Vec<EdgeCost>) {
+    // Counting is cheap, so it is always done and only handed out if requested
+    let mut stats = SearchStats::default();
+    let result = dijkstra_counted::<BinaryQueue, C>(graph, costs, start, direction, done, trace, &mut stats);
+    if trace.stats.is_some() {
+        trace.stats = Some(stats);
+    }
+    result
+}
+
+fn dijkstra_counted<Q: PriorityQueue, C: CostFunction + ?Sized>(graph: &Graph, costs: &C, start: usize, direction: Direction, mut done: impl FnMut(usize) -> bool, trace: &mut SearchTrace, stats: &mut SearchStats) -> (EdgeCost, Vec<EdgeCost>) {
+    let mut heap = Q::new(graph.node_count());
+    let mut dist = (0..graph.node_count()).map(|_| EdgeCost::MAX).collect::<Vec<EdgeCost>>();
+
+    if let Some(pred) = trace.pred.as_mut() {
+        pred.clear();
+        pred.resize(graph.node_count(), usize::MAX);
+    }
+
+    dist[start] = 0;
+    heap.push(start, 0);
+    stats.heap_pushes += 1;
+    stats.peak_heap_size = 1;
+
+    while let Some((position, cost)) = heap.pop() {
+        stats.heap_pops += 1;
         if cost > dist[position] {
+            stats.stale_skipped += 1;
             continue;
         }
 
-        for edge in graph.outgoing_edges(position) {
-            let edge_cost = edge.cost;
-            let next = State { cost: cost + edge_cost, position: edge.trg as usize };
+        stats.settled_nodes += 1;
+
+        if let Some(settled) = trace.settled.as_mut() {
+            settled.push(position);
+        }
 
-            if next.cost < dist[next.position] {
-                heap.push(next);
-                dist[next.position] = next.cost;
+        if done(position) {
+            return (cost, dist);
+        }
+
+        for edge_position in direction.edge_range(graph, position) {
+            let edge_index = direction.edge_index(edge_position);
+            if !costs.allows(graph, edge_index) {
+                continue;
+            }
+            let edge_cost = costs.cost(graph, edge_index);
+            let edge = graph.edge(edge_index).unwrap();
+            // Saturates instead of wrapping around, `EdgeCost::MAX` is never an improvement
+            let (next, next_cost) = (direction.next(edge), cost.saturating_add(edge_cost));
+            stats.relaxed_edges += 1;
+
+            if next_cost < dist[next] {
+                if heap.push(next, next_cost) {
+                    stats.heap_pushes += 1;
+                } else {
+                    stats.decrease_keys += 1;
+                }
+                stats.peak_heap_size = stats.peak_heap_size.max(heap.len() as u64);
+                dist[next] = next_cost;
+                if let Some(pred) = trace.pred.as_mut() {
+                    pred[next] = edge_index;
+                }
             }
         }
     }
 
     (-1, dist)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{answer_query, dijkstra_counted, parse_via_query, shortest_path, shortest_path_fallback, shortest_path_traced, shortest_path_tree, shortest_path_tree_with, shortest_paths_to, shortest_route, via_route, Direction, Location, SearchStats, SearchTrace, SolveOptions};
+    use crate::coords::Coords;
+    use crate::cost::StoredCost;
+    use crate::generate::fixture;
+    use crate::graph::IncomingEdges;
+    use crate::grid::{SnapFilter, SnapTree};
+    use crate::queue::QuaternaryHeap;
+    use crate::Graph;
+
+    /// The nodes lie 0.001° apart from west to east
+    fn graph() -> Graph {
+        fixture("detour")
+    }
+
+    #[test]
+    fn route() {
+        let (cost, path) = shortest_route(&graph(), 0, 2).unwrap();
+        assert_eq!(cost, 4);
+        assert_eq!(path.nodes(), vec![0, 3, 1, 2]);
+        assert!(shortest_route(&graph(), 2, 0).is_none());
+    }
+
+    #[test]
+    fn tree() {
+        let graph = graph();
+        let tree = shortest_path_tree(&graph, 0);
+        assert_eq!(tree.walk_to_root(&graph, 2).collect::<Vec<_>>(), vec![2, 1, 3, 0]);
+        assert_eq!(tree.path_to(&graph, 2).unwrap().cost(), 4);
+        assert_eq!(tree.path_to(&graph, 0).unwrap().path.len(), 0);
+        let mut subtree = tree.subtree(&graph, 3);
+        subtree.sort();
+        assert_eq!(subtree, vec![1, 2, 3]);
+
+        let tree = shortest_path_tree(&graph, 2);
+        assert!(tree.path_to(&graph, 0).is_none());
+        assert_eq!(tree.walk_to_root(&graph, 0).count(), 0);
+        assert!(tree.subtree(&graph, 0).is_empty());
+    }
+
+    #[test]
+    fn all_to_one() {
+        let graph = graph();
+        let expected = (0..4)
+            .map(|start| match shortest_path(&graph, start, 2) { -1 => i64::MAX, cost => cost })
+            .collect::<Vec<_>>();
+        let incoming = IncomingEdges::new(&graph);
+        assert_eq!(shortest_paths_to(&graph, &incoming, 2), expected);
+
+        let tree = shortest_path_tree_with(&graph, &StoredCost, 2, Direction::Backward(&incoming));
+        assert_eq!(tree.dist, expected);
+        assert_eq!(tree.walk_to_root(&graph, 0).collect::<Vec<_>>(), vec![0, 3, 1, 2]);
+        assert_eq!(tree.path_to(&graph, 0).unwrap().nodes(), vec![0, 3, 1, 2]);
+        assert_eq!(tree.subtree(&graph, 1), vec![1, 3, 0]);
+    }
+
+    #[test]
+    fn fallback() {
+        let mut trace = SearchTrace::default();
+        // Node 2 has no outgoing edges, so the second start candidate has to be used
+        assert_eq!(shortest_path_fallback(&graph(), &StoredCost, &[2, 3], &[1], &mut trace), Some((3, 1, 1)));
+        assert_eq!(shortest_path_fallback(&graph(), &StoredCost, &[2], &[0, 1], &mut trace), None);
+    }
+
+    #[test]
+    fn via() {
+        let graph = graph();
+        let mut trace = SearchTrace::default().with_stats().with_pred();
+        let route = via_route(&graph, &StoredCost, &[0, 1, 2], &mut trace).unwrap();
+        assert_eq!(route.legs.iter().map(|leg| leg.cost).collect::<Vec<_>>(), vec![3, 1]);
+        assert_eq!(route.cost(), 4);
+        assert_eq!(route.path().unwrap().nodes(), vec![0, 3, 1, 2]);
+        assert_eq!(trace.stats.unwrap().settled_nodes, 5);
+        // Without predecessors there are no paths
+        assert!(via_route(&graph, &StoredCost, &[0, 1, 2], &mut SearchTrace::default()).unwrap().path().is_none());
+        assert_eq!(via_route(&graph, &StoredCost, &[0, 2, 1], &mut trace).err(), Some(1));
+
+        let (waypoints, profile) = parse_via_query("0 48.1,9.2 7 bicycle").unwrap();
+        assert_eq!(waypoints, vec![Location::Node(0), Location::Coords(Coords::deg(48.1, 9.2)), Location::Node(7)]);
+        assert_eq!(profile, Some("bicycle"));
+        assert_eq!(parse_via_query("3 5").unwrap().1, None);
+        assert!(parse_via_query("3 car").is_none());
+        assert!(parse_via_query("3 x 5").is_none());
+    }
+
+    #[test]
+    fn stats() {
+        let mut trace = SearchTrace::default().with_stats().with_settled();
+        shortest_path_traced(&graph(), 0, usize::MAX, &mut trace);
+        let stats = trace.stats.unwrap();
+
+        assert_eq!(stats.settled_nodes, 4);
+        assert_eq!(stats.settled_nodes, trace.settled.unwrap().len() as u64);
+        assert_eq!(stats.relaxed_edges, 5);
+        // Nodes 1 and 2 are both reached once more cheaply over 3
+        assert_eq!(stats.stale_skipped, 2);
+        assert_eq!(stats.heap_pops, stats.heap_pushes);
+        assert_eq!(stats.decrease_keys, 0);
+
+        // The d-ary heap lowers the queued entries of 1 and 2 instead
+        let mut stats = SearchStats::default();
+        dijkstra_counted::<QuaternaryHeap, _>(&graph(), &StoredCost, 0, Direction::Forward, |_| false, &mut SearchTrace::default(), &mut stats);
+        assert_eq!(stats.decrease_keys, 2);
+        assert_eq!(stats.stale_skipped, 0);
+        assert_eq!(stats.heap_pops, stats.heap_pushes);
+    }
+
+    #[test]
+    fn answers() {
+        let graph = graph();
+        let options = SolveOptions::default();
+        let answer = |line: &str| answer_query(&graph, line, &options).to_string();
+        assert_eq!(answer("0 2"), "4");
+        assert_eq!(answer("0 1 2"), "4 = 3 + 1");
+        assert_eq!(answer("2 0"), "-1");
+        assert_eq!(answer("0 7"), "error: 7 is not a node of the graph");
+        assert!(answer("0 2 plane").starts_with("error: unknown profile 'plane'"));
+        assert!(answer("0").starts_with("error: expected"));
+        assert_eq!(answer("48.0,9.0 48.0,9.0019"), "4");
+
+        // Avoiding node 3 leaves only the direct edge to 1
+        let options = SolveOptions { blocked: Arc::new(vec![false, false, false, true]), ..SolveOptions::default() };
+        assert_eq!(answer_query(&graph, "0 2", &options).to_string(), "6");
+        assert_eq!(answer_query(&graph, "0 3", &options).to_string(), "-1");
+
+        // Node 2 has no outgoing edges, so coordinates next to it are snapped to 1
+        let options = SolveOptions { snap: SnapTree::new(SnapFilter::Outgoing), track_paths: true, ..SolveOptions::default() };
+        let result = answer_query(&graph, "0 48.0,9.0018", &options);
+        assert_eq!(result.cost, 3);
+        assert_eq!(result.path.unwrap().nodes(), vec![0, 3, 1]);
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use owo_colors::OwoColorize;
+
+use crate::overlay;
+use crate::router::{self, SolveOptions};
+use crate::Graph;
+
+/// Listens on `addr` and answers every query line like `<waypoint> <waypoint> ... [profile]`, each
+/// waypoint a node id or `<lat>,<lon>`, with the cost of the metric of the options, or the travel
+/// time in milliseconds if a profile is given. Routes over via points are answered like
+/// `cost = leg + leg`. Invalid queries are answered with `error: <reason>`. Lines with a cost update like
+/// `block 3,5` or `reset` are answered with `ok` and change the costs for the following queries of
+/// the same connection, on top of the updates of the options. Every connection is handled by its
+/// own thread.
+pub fn serve(graph: Arc<Graph>, options: SolveOptions, addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("{}", format!("Listening on {}...", listener.local_addr()?).yellow());
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let graph = graph.clone();
+        let options = options.clone();
+        thread::spawn(move || {
+            if let Err(err) = handle_client(graph.as_ref(), &options, stream) {
+                println!("{}", format!("Connection failed: {}", err).red());
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_client(graph: &Graph, options: &SolveOptions, stream: TcpStream) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    // The updates are copied on the first command, so other connections keep theirs
+    let mut options = options.clone();
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if overlay::is_command(&line) {
+            match Arc::make_mut(&mut options.overlay).execute(graph, &line) {
+                Ok(()) => writeln!(writer, "ok")?,
+                Err(err) => writeln!(writer, "error: {}", err)?,
+            }
+            continue;
+        }
+        writeln!(writer, "{}", router::answer_query(graph, &line, &options))?;
+    }
+
+    Ok(())
+}
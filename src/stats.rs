@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter};
+use std::mem::size_of;
+
+use serde_json::{json, Value};
+
+use crate::coords::BoundingBox;
+use crate::edge::{Edge, EdgeCost};
+use crate::grid::NodeTree;
+use crate::node::Node;
+use crate::Graph;
+
+/// The numbers describing a loaded graph
+pub struct GraphStats {
+    pub node_count: usize,
+    pub edge_count: usize,
+    /// How many nodes have an out-degree of the index
+    pub out_degrees: Vec<usize>,
+    /// How many nodes have an in-degree of the index
+    pub in_degrees: Vec<usize>,
+    pub min_cost: Option<EdgeCost>,
+    pub max_cost: Option<EdgeCost>,
+    pub mean_cost: Option<f64>,
+    pub self_loops: usize,
+    /// Edges which have the same source and target as an earlier edge
+    pub parallel_edges: usize,
+    pub nodes_without_outgoing: usize,
+    pub bbox: Option<BoundingBox>,
+    pub nodes_bytes: usize,
+    pub edges_bytes: usize,
+    pub elevations_bytes: usize,
+    /// Only present if the tree was built
+    pub tree_bytes: Option<usize>,
+}
+
+impl GraphStats {
+    pub fn compute(graph: &Graph, tree: Option<&NodeTree>) -> GraphStats {
+        let mut out_degrees = Vec::new();
+        let mut in_degree = vec![0usize; graph.node_count()];
+        let mut self_loops = 0;
+        let mut parallel_edges = 0;
+        let mut targets = Vec::new();
+
+        for node in 0..graph.node_count() {
+            let edges = graph.outgoing_edges(node);
+            increment(&mut out_degrees, edges.len());
+
+            targets.clear();
+            targets.extend(edges.iter().map(|edge| edge.trg));
+            targets.sort_unstable();
+            parallel_edges += targets.windows(2).filter(|pair| pair[0] == pair[1]).count();
+
+            for edge in edges {
+                in_degree[edge.trg as usize] += 1;
+                if edge.trg == edge.src {
+                    self_loops += 1;
+                }
+            }
+        }
+
+        let mut in_degrees = Vec::new();
+        in_degree.into_iter().for_each(|degree| increment(&mut in_degrees, degree));
+
+        let costs = graph.edges().iter().map(|edge| edge.cost);
+        let total_cost = costs.clone().map(|cost| cost as f64).sum::<f64>();
+
+        GraphStats {
+            node_count: graph.node_count(),
+            edge_count: graph.edge_count(),
+            nodes_without_outgoing: out_degrees.first().copied().unwrap_or(0),
+            out_degrees,
+            in_degrees,
+            min_cost: costs.clone().min(),
+            max_cost: costs.max(),
+            mean_cost: (graph.edge_count() > 0).then(|| total_cost / graph.edge_count() as f64),
+            self_loops,
+            parallel_edges,
+            bbox: BoundingBox::around(graph.nodes().iter().map(|node| &node.coords)),
+            nodes_bytes: graph.nodes().capacity() * size_of::<Node>(),
+            edges_bytes: graph.edges().capacity() * size_of::<Edge>(),
+            elevations_bytes: graph.elevations().capacity() * size_of::<f64>(),
+            tree_bytes: tree.map(NodeTree::memory_size),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "nodes": self.node_count,
+            "edges": self.edge_count,
+            "out_degrees": self.out_degrees,
+            "in_degrees": self.in_degrees,
+            "cost": { "min": self.min_cost, "max": self.max_cost, "mean": self.mean_cost },
+            "self_loops": self.self_loops,
+            "parallel_edges": self.parallel_edges,
+            "nodes_without_outgoing_edges": self.nodes_without_outgoing,
+            "bbox": self.bbox.map(|bbox| json!({
+                "min_lat": bbox.min.lat,
+                "min_lon": bbox.min.lon,
+                "max_lat": bbox.max.lat,
+                "max_lon": bbox.max.lon,
+            })),
+            "memory_bytes": {
+                "nodes": self.nodes_bytes,
+                "edges": self.edges_bytes,
+                "elevations": self.elevations_bytes,
+                "tree": self.tree_bytes,
+            },
+        })
+    }
+}
+
+fn increment(histogram: &mut Vec<usize>, index: usize) {
+    if histogram.len() <= index {
+        histogram.resize(index + 1, 0);
+    }
+    histogram[index] += 1;
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+fn or_none<T: Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_string(), |value| value.to_string())
+}
+
+impl Display for GraphStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Nodes:                  {}", self.node_count)?;
+        writeln!(f, "Edges:                  {}", self.edge_count)?;
+        writeln!(f, "Edge cost:              min {}, max {}, mean {}",
+                 or_none(self.min_cost), or_none(self.max_cost), or_none(self.mean_cost.map(|mean| format!("{:.2}", mean))))?;
+        writeln!(f, "Self-loops:             {}", self.self_loops)?;
+        writeln!(f, "Parallel edges:         {}", self.parallel_edges)?;
+        writeln!(f, "Without outgoing edges: {}", self.nodes_without_outgoing)?;
+        writeln!(f, "Bounding box:           {}", or_none(self.bbox))?;
+        writeln!(f, "Degree   out-degree  in-degree")?;
+        for degree in 0..self.out_degrees.len().max(self.in_degrees.len()) {
+            writeln!(f, "{:>6} {:>12} {:>10}", degree,
+                     self.out_degrees.get(degree).unwrap_or(&0), self.in_degrees.get(degree).unwrap_or(&0))?;
+        }
+        writeln!(f, "Memory:                 nodes {:.1} MiB, edges {:.1} MiB, elevations {:.1} MiB, tree {}",
+                 mib(self.nodes_bytes), mib(self.edges_bytes), mib(self.elevations_bytes),
+                 or_none(self.tree_bytes.map(|bytes| format!("{:.1} MiB", mib(bytes)))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GraphStats;
+    use crate::coords::Coords;
+    use crate::edge::Edge;
+    use crate::Graph;
+
+    #[test]
+    fn counts() {
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(49.0, 8.0), Coords::deg(48.5, 10.0)];
+        let edges = vec![Edge::new(0, 1, 4), Edge::new(0, 1, 6), Edge::new(1, 1, 2), Edge::new(1, 0, 8)];
+        let stats = GraphStats::compute(&Graph::from_edges(coords, edges), None);
+
+        assert_eq!(stats.out_degrees, vec![1, 0, 2]);
+        assert_eq!(stats.in_degrees, vec![1, 1, 0, 1]);
+        assert_eq!((stats.min_cost, stats.max_cost, stats.mean_cost), (Some(2), Some(8), Some(5.0)));
+        assert_eq!(stats.self_loops, 1);
+        assert_eq!(stats.parallel_edges, 1);
+        assert_eq!(stats.nodes_without_outgoing, 1);
+        let bbox = stats.bbox.unwrap();
+        assert_eq!((bbox.min, bbox.max), (Coords::deg(48.0, 8.0), Coords::deg(49.0, 10.0)));
+    }
+}
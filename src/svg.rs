@@ -0,0 +1,174 @@
+use std::f64::consts::FRAC_PI_4;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+use crate::coords::{BoundingBox, Coords};
+use crate::edge::EdgePath;
+use crate::grid::NodeTree;
+use crate::Graph;
+
+/// How coordinates are mapped onto the plane
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Projection {
+    Equirectangular,
+    WebMercator,
+}
+
+impl Projection {
+    /// Projects to `(x, y)` with `y` growing northwards
+    pub fn project(&self, coords: &Coords) -> (f64, f64) {
+        match self {
+            Projection::Equirectangular => (coords.lon, coords.lat),
+            Projection::WebMercator => {
+                // Web Mercator is undefined at the poles
+                let lat = coords.lat.clamp(-85.051129, 85.051129).to_radians();
+                (coords.lon.to_radians(), (FRAC_PI_4 + lat / 2.0).tan().ln())
+            }
+        }
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "equirectangular" => Ok(Projection::Equirectangular),
+            "web-mercator" | "mercator" => Ok(Projection::WebMercator),
+            _ => Err(format!("Unknown projection '{}', expected 'equirectangular' or 'web-mercator'", s)),
+        }
+    }
+}
+
+/// Draws layers of a graph inside a bounding box into an SVG image. Later layers are drawn on top.
+pub struct SvgRenderer {
+    bbox: BoundingBox,
+    projection: Projection,
+    /// The projected lower left corner
+    origin: (f64, f64),
+    scale: f64,
+    width: f64,
+    height: f64,
+    body: String,
+}
+
+impl SvgRenderer {
+    pub fn new(bbox: BoundingBox, projection: Projection, width: u32) -> Self {
+        let min = projection.project(&bbox.min);
+        let max = projection.project(&bbox.max);
+        let width = width as f64;
+        let scale = width / (max.0 - min.0).max(f64::EPSILON);
+        let height = ((max.1 - min.1) * scale).max(1.0);
+
+        Self { bbox, projection, origin: min, scale, width, height, body: String::new() }
+    }
+
+    fn point(&self, coords: &Coords) -> (f64, f64) {
+        let (x, y) = self.projection.project(coords);
+        ((x - self.origin.0) * self.scale, self.height - (y - self.origin.1) * self.scale)
+    }
+
+    fn coords(graph: &Graph, index: usize) -> &Coords {
+        &graph.node(index).expect("Node is not part of the graph").coords
+    }
+
+    /// All edges with at least one end inside the box
+    pub fn draw_edges(&mut self, graph: &Graph) {
+        self.body.push_str(r##"<g stroke="#888" stroke-width="0.5">"##);
+        for (src, node) in graph.nodes().iter().enumerate() {
+            for edge in graph.outgoing_edges(src) {
+                let trg = Self::coords(graph, edge.trg as usize);
+                if self.bbox.contains(&node.coords) || self.bbox.contains(trg) {
+                    self.line(&node.coords, trg);
+                }
+            }
+        }
+        self.body.push_str("</g>\n");
+    }
+
+    pub fn draw_path(&mut self, graph: &Graph, path: &EdgePath) {
+        let points = path.nodes().iter()
+            .map(|node| self.point(Self::coords(graph, *node as usize)))
+            .map(|(x, y)| format!("{:.2},{:.2}", x, y))
+            .collect::<Vec<_>>();
+        writeln!(self.body, r##"<polyline fill="none" stroke="#d62728" stroke-width="3" points="{}"/>"##, points.join(" ")).unwrap();
+    }
+
+    /// Marks nodes, e.g. the ones settled by dijkstra
+    pub fn draw_nodes(&mut self, graph: &Graph, nodes: &[usize]) {
+        self.body.push_str(r##"<g fill="#1f77b4" fill-opacity="0.6">"##);
+        for node in nodes {
+            let coords = Self::coords(graph, *node);
+            if self.bbox.contains(coords) {
+                let (x, y) = self.point(coords);
+                write!(self.body, r#"<circle cx="{:.2}" cy="{:.2}" r="1.5"/>"#, x, y).unwrap();
+            }
+        }
+        self.body.push_str("</g>\n");
+    }
+
+    /// The boundaries of the quadrants of the tree which intersect the box
+    pub fn draw_quadrants(&mut self, tree: &NodeTree) {
+        self.body.push_str(r##"<g fill="none" stroke="#2ca02c" stroke-width="0.5">"##);
+        for (center, size) in tree.quadrants() {
+            let half = size / 2.0;
+            let min = Coords::deg(center.lat - half, center.lon - half);
+            let max = Coords::deg(center.lat + half, center.lon + half);
+            if min.lat > self.bbox.max.lat || max.lat < self.bbox.min.lat
+                || min.lon > self.bbox.max.lon || max.lon < self.bbox.min.lon {
+                continue;
+            }
+
+            let (x1, y1) = self.point(&Coords::deg(max.lat, min.lon));
+            let (x2, y2) = self.point(&Coords::deg(min.lat, max.lon));
+            write!(self.body, r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}"/>"#, x1, y1, x2 - x1, y2 - y1).unwrap();
+        }
+        self.body.push_str("</g>\n");
+    }
+
+    fn line(&mut self, from: &Coords, to: &Coords) {
+        let (x1, y1) = self.point(from);
+        let (x2, y2) = self.point(to);
+        write!(self.body, r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}"/>"#, x1, y1, x2, y2).unwrap();
+    }
+
+    pub fn finish(self) -> String {
+        format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#, "\n",
+                r#"<rect width="100%" height="100%" fill="white"/>"#, "\n",
+                "{2}</svg>\n"
+            ),
+            self.width, self.height, self.body
+        )
+    }
+
+    pub fn write_file(self, path: &str) -> io::Result<()> {
+        fs::write(path, self.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Projection, SvgRenderer};
+    use crate::coords::{BoundingBox, Coords};
+
+    #[test]
+    fn corners() {
+        let bbox = BoundingBox::new(Coords::deg(48.0, 9.0), Coords::deg(49.0, 11.0));
+        let renderer = SvgRenderer::new(bbox, Projection::Equirectangular, 200);
+
+        assert_eq!(renderer.height, 100.0);
+        assert_eq!(renderer.point(&Coords::deg(49.0, 9.0)), (0.0, 0.0));
+        assert_eq!(renderer.point(&Coords::deg(48.0, 11.0)), (200.0, 100.0));
+    }
+
+    #[test]
+    fn mercator_stretches_north() {
+        let south = Projection::WebMercator.project(&Coords::deg(10.0, 0.0)).1;
+        let north = Projection::WebMercator.project(&Coords::deg(60.0, 0.0)).1 - Projection::WebMercator.project(&Coords::deg(50.0, 0.0)).1;
+        assert!(north > south);
+    }
+}
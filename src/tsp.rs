@@ -0,0 +1,332 @@
+use std::fmt::{Display, Formatter};
+
+use crate::cost::CostFunction;
+use crate::edge::EdgeCost;
+use crate::router::{self, SearchTrace};
+use crate::Graph;
+
+/// Tours with at most this many stops besides the start and end are solved exactly
+pub const EXACT_LIMIT: usize = 12;
+
+/// Where a tour ends
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TourKind {
+    /// Back at the start
+    RoundTrip,
+    /// At whichever stop is visited last
+    Open,
+    /// At the last location, which is not reordered
+    FixedEnd,
+}
+
+impl Display for TourKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TourKind::RoundTrip => write!(f, "round-trip"),
+            TourKind::Open => write!(f, "open"),
+            TourKind::FixedEnd => write!(f, "fixed-end"),
+        }
+    }
+}
+
+/// A visiting order as indices into the cost matrix. It starts with 0 and, for round trips, returns
+/// to it without repeating it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Tour {
+    pub order: Vec<usize>,
+    pub cost: EdgeCost,
+    /// Whether the order is the best possible one
+    pub exact: bool,
+}
+
+/// The costs between all pairs of nodes with one dijkstra per node, which stops once all nodes are
+/// settled. `EdgeCost::MAX` if a node can't reach another.
+pub fn cost_matrix(graph: &Graph, costs: &dyn CostFunction, nodes: &[usize]) -> Vec<Vec<EdgeCost>> {
+    nodes.iter()
+        .map(|start| router::shortest_paths_among(graph, costs, *start, nodes, &mut SearchTrace::default()))
+        .collect()
+}
+
+/// The cost of visiting the matrix indices in order, `EdgeCost::MAX` if a leg is impossible
+pub fn tour_cost(matrix: &[Vec<EdgeCost>], order: &[usize], kind: TourKind) -> EdgeCost {
+    let back = (kind == TourKind::RoundTrip).then(|| (order[order.len() - 1], order[0]));
+    order.windows(2).map(|pair| (pair[0], pair[1])).chain(back)
+        .map(|(from, to)| matrix[from][to])
+        .try_fold(0 as EdgeCost, |total, cost| (cost != EdgeCost::MAX).then(|| total + cost))
+        .unwrap_or(EdgeCost::MAX)
+}
+
+/// Finds a good order to visit all matrix indices, starting at 0. With `TourKind::FixedEnd` the
+/// last index is the end. Small tours are solved exactly unless `heuristic` is set, larger ones
+/// with nearest neighbour followed by 2-opt and Or-opt. `None` if no order reaches every stop.
+pub fn solve(matrix: &[Vec<EdgeCost>], kind: TourKind, heuristic: bool) -> Option<Tour> {
+    let stop_count = matrix.len().saturating_sub(if kind == TourKind::FixedEnd { 2 } else { 1 });
+    let tour = if !heuristic && stop_count <= EXACT_LIMIT {
+        Tour { order: held_karp(matrix, kind), cost: 0, exact: true }
+    } else {
+        let mut order = nearest_neighbour(matrix, kind);
+        improve(matrix, &mut order, kind);
+        Tour { order, cost: 0, exact: false }
+    };
+
+    let cost = tour_cost(matrix, &tour.order, kind);
+    (cost != EdgeCost::MAX).then_some(Tour { cost, ..tour })
+}
+
+/// The stops which may be reordered, i.e. all but the start and a fixed end
+fn stops(count: usize, kind: TourKind) -> Vec<usize> {
+    let end = if kind == TourKind::FixedEnd { count - 1 } else { count };
+    (1..end).collect()
+}
+
+fn with_end(mut order: Vec<usize>, count: usize, kind: TourKind) -> Vec<usize> {
+    if kind == TourKind::FixedEnd && count > 1 {
+        order.push(count - 1);
+    }
+    order
+}
+
+/// Exact dynamic programming over the subsets of stops in O(2^n n^2)
+fn held_karp(matrix: &[Vec<EdgeCost>], kind: TourKind) -> Vec<usize> {
+    let stops = stops(matrix.len(), kind);
+    let add = |a: EdgeCost, b: EdgeCost| if a == EdgeCost::MAX || b == EdgeCost::MAX { EdgeCost::MAX } else { a + b };
+    // The cost of leaving the last stop at the end of the tour
+    let finish = |last: usize| match kind {
+        TourKind::RoundTrip => matrix[last][0],
+        TourKind::Open => 0,
+        TourKind::FixedEnd => matrix[last][matrix.len() - 1],
+    };
+    if stops.is_empty() {
+        return with_end(vec![0], matrix.len(), kind);
+    }
+
+    // best[set][i] is the cheapest way from the start over all stops of `set` ending at stop i
+    let n = stops.len();
+    let mut best = vec![vec![EdgeCost::MAX; n]; 1 << n];
+    let mut parent = vec![vec![usize::MAX; n]; 1 << n];
+    for i in 0..n {
+        best[1 << i][i] = matrix[0][stops[i]];
+    }
+    for set in 1..(1usize << n) {
+        for last in (0..n).filter(|last| set & (1 << last) != 0) {
+            if best[set][last] == EdgeCost::MAX {
+                continue;
+            }
+            for next in (0..n).filter(|next| set & (1 << next) == 0) {
+                let cost = add(best[set][last], matrix[stops[last]][stops[next]]);
+                let next_set = set | (1 << next);
+                if cost < best[next_set][next] {
+                    best[next_set][next] = cost;
+                    parent[next_set][next] = last;
+                }
+            }
+        }
+    }
+
+    let full = (1 << n) - 1;
+    let mut last = (0..n).min_by_key(|last| add(best[full][*last], finish(stops[*last]))).unwrap();
+    if add(best[full][last], finish(stops[last])) == EdgeCost::MAX {
+        // Some stop can't be reached, any complete order shows that
+        return nearest_neighbour(matrix, kind);
+    }
+    let mut set = full;
+    let mut order = Vec::new();
+    while last != usize::MAX {
+        order.push(stops[last]);
+        let previous = parent[set][last];
+        set &= !(1 << last);
+        last = previous;
+    }
+    order.push(0);
+    order.reverse();
+    with_end(order, matrix.len(), kind)
+}
+
+/// Always goes to the closest stop not visited yet
+fn nearest_neighbour(matrix: &[Vec<EdgeCost>], kind: TourKind) -> Vec<usize> {
+    let mut remaining = stops(matrix.len(), kind);
+    let mut order = vec![0];
+    while !remaining.is_empty() {
+        let last = order[order.len() - 1];
+        let closest = (0..remaining.len()).min_by_key(|i| matrix[last][remaining[*i]]).unwrap();
+        order.push(remaining.swap_remove(closest));
+    }
+    with_end(order, matrix.len(), kind)
+}
+
+/// What an impossible leg adds to the cost of a tour while improving it, more than any tour of
+/// possible legs costs
+const UNREACHABLE: i128 = 1 << 96;
+
+fn leg(matrix: &[Vec<EdgeCost>], from: usize, to: usize) -> i128 {
+    match matrix[from][to] {
+        EdgeCost::MAX => UNREACHABLE,
+        cost => cost as i128,
+    }
+}
+
+/// The index at `position` of the order, or the start after the last one of a round trip. `None`
+/// if the tour ends before.
+fn at(order: &[usize], position: usize, kind: TourKind) -> Option<usize> {
+    match order.get(position) {
+        Some(index) => Some(*index),
+        None => (kind == TourKind::RoundTrip).then(|| order[0]),
+    }
+}
+
+/// Applies improving 2-opt moves (reversing a part of the tour) and Or-opt moves (moving up to
+/// three consecutive stops elsewhere) until there are none left. Every move is evaluated by the
+/// legs it changes. Costs may be asymmetric, so 2-opt also sums the legs inside the reversed part in
+/// both directions, which grows by one leg per move.
+fn improve(matrix: &[Vec<EdgeCost>], order: &mut Vec<usize>, kind: TourKind) {
+    let leg = |from: usize, to: usize| leg(matrix, from, to);
+    // The start and a fixed end stay in place
+    let end = if kind == TourKind::FixedEnd { order.len() - 1 } else { order.len() };
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..end {
+            // The legs inside order[i..=j] as they are and reversed
+            let (mut forward, mut backward) = (0, 0);
+            for j in (i + 1)..end {
+                forward += leg(order[j - 1], order[j]);
+                backward += leg(order[j], order[j - 1]);
+                let (before, after) = (order[i - 1], at(order, j + 1, kind));
+                let old = leg(before, order[i]) + forward + after.map_or(0, |after| leg(order[j], after));
+                let new = leg(before, order[j]) + backward + after.map_or(0, |after| leg(order[i], after));
+                if new < old {
+                    order[i..=j].reverse();
+                    std::mem::swap(&mut forward, &mut backward);
+                    improved = true;
+                }
+            }
+        }
+
+        for length in 1..=3 {
+            for i in 1..end.saturating_sub(length - 1) {
+                for target in 1..=(end - length) {
+                    if target == i {
+                        continue;
+                    }
+                    let (first, last) = (order[i], order[i + length - 1]);
+                    let (before, after) = (order[i - 1], at(order, i + length, kind));
+                    let removed = leg(before, first) + after.map_or(0, |after| leg(last, after) - leg(before, after));
+                    // The neighbours of the gap at `target` once the stops are taken out
+                    let position = |k: usize| if k < i { k } else { k + length };
+                    let (previous, next) = (order[position(target - 1)], at(order, position(target), kind));
+                    let inserted = leg(previous, first) + next.map_or(0, |next| leg(last, next) - leg(previous, next));
+                    if inserted < removed {
+                        let segment = order.drain(i..i + length).collect::<Vec<_>>();
+                        order.splice(target..target, segment);
+                        improved = true;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cost_matrix, solve, tour_cost, TourKind};
+    use crate::cost::StoredCost;
+    use crate::edge::EdgeCost;
+    use crate::generate::grid;
+    use crate::rng::Rng;
+
+    /// The cheapest order by trying all permutations of the stops
+    fn brute_force(matrix: &[Vec<EdgeCost>], kind: TourKind) -> EdgeCost {
+        fn permute(stops: &mut Vec<usize>, k: usize, visit: &mut impl FnMut(&[usize])) {
+            if k == stops.len() {
+                return visit(stops);
+            }
+            for i in k..stops.len() {
+                stops.swap(k, i);
+                permute(stops, k + 1, visit);
+                stops.swap(k, i);
+            }
+        }
+
+        let end = if kind == TourKind::FixedEnd { matrix.len() - 1 } else { matrix.len() };
+        let mut best = EdgeCost::MAX;
+        permute(&mut (1..end).collect(), 0, &mut |stops| {
+            let mut order = vec![0];
+            order.extend(stops);
+            if kind == TourKind::FixedEnd {
+                order.push(matrix.len() - 1);
+            }
+            best = best.min(tour_cost(matrix, &order, kind));
+        });
+        best
+    }
+
+    #[test]
+    fn orders() {
+        let graph = grid(12, 12, 20, 4);
+        let mut rng = Rng::new(9);
+        let nodes = (0..8).map(|_| rng.below(graph.node_count() as u64) as usize).collect::<Vec<_>>();
+        let matrix = cost_matrix(&graph, &StoredCost, &nodes);
+
+        for kind in [TourKind::RoundTrip, TourKind::Open, TourKind::FixedEnd] {
+            let exact = solve(&matrix, kind, false).unwrap();
+            assert!(exact.exact);
+            assert_eq!(exact.order[0], 0);
+            assert_eq!(exact.order.len(), nodes.len());
+            assert_eq!(exact.cost, brute_force(&matrix, kind), "{}", kind);
+            if kind == TourKind::FixedEnd {
+                assert_eq!(exact.order[nodes.len() - 1], nodes.len() - 1);
+            }
+
+            let heuristic = solve(&matrix, kind, true).unwrap();
+            let mut visited = heuristic.order.clone();
+            visited.sort();
+            assert_eq!(visited, (0..nodes.len()).collect::<Vec<_>>());
+            assert!(heuristic.cost >= exact.cost);
+        }
+    }
+
+    #[test]
+    fn local_optimum() {
+        // Asymmetric costs with some impossible legs
+        let mut rng = Rng::new(4);
+        let matrix = (0..20)
+            .map(|from| (0..20).map(|to| match rng.below(20) {
+                _ if from == to => 0,
+                0 => EdgeCost::MAX,
+                _ => 1 + rng.below(1000) as EdgeCost,
+            }).collect())
+            .collect::<Vec<Vec<_>>>();
+
+        for kind in [TourKind::RoundTrip, TourKind::Open, TourKind::FixedEnd] {
+            let mut order = super::nearest_neighbour(&matrix, kind);
+            let start_cost = tour_cost(&matrix, &order, kind);
+            super::improve(&matrix, &mut order, kind);
+            let cost = tour_cost(&matrix, &order, kind);
+            assert_ne!(cost, EdgeCost::MAX, "{}", kind);
+            assert!(cost <= start_cost);
+            // No single move evaluated on the whole tour is an improvement
+            let end = if kind == TourKind::FixedEnd { order.len() - 1 } else { order.len() };
+            for i in 1..end {
+                for j in (i + 1)..end {
+                    let mut moved = order.clone();
+                    moved[i..=j].reverse();
+                    assert!(tour_cost(&matrix, &moved, kind) >= cost, "{}", kind);
+                }
+                for target in 1..end {
+                    let mut moved = order.clone();
+                    let stop = moved.remove(i);
+                    moved.insert(target, stop);
+                    assert!(tour_cost(&matrix, &moved, kind) >= cost, "{}", kind);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable() {
+        let matrix = vec![vec![0, 5, EdgeCost::MAX], vec![5, 0, EdgeCost::MAX], vec![EdgeCost::MAX, EdgeCost::MAX, 0]];
+        assert_eq!(solve(&matrix, TourKind::Open, false), None);
+        assert_eq!(solve(&matrix, TourKind::Open, true), None);
+        assert_eq!(solve(&[vec![0]], TourKind::RoundTrip, false).unwrap().order, vec![0]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::{Display, Formatter};
+
+use crate::edge::EdgeCost;
+use crate::node::NodeIndex;
+use crate::Graph;
+
+/// A single inconsistency found in a graph
+#[derive(Clone, PartialEq, Debug)]
+pub enum Problem {
+    /// The graph file declared a different amount of nodes or edges than it contained
+    CountMismatch { what: &'static str, declared: usize, actual: usize },
+    /// The offset of a node points outside of the edge array or was never set
+    OffsetOutOfRange { node: usize, offset: NodeIndex },
+    /// The offset of a node is smaller than the one of the node before it
+    OffsetNotMonotone { node: usize, offset: NodeIndex, previous: NodeIndex },
+    /// An edge is listed under a node which isn't its source
+    WrongSource { edge: usize, src: NodeIndex, node: usize },
+    TargetOutOfRange { edge: usize, trg: NodeIndex },
+    /// Dijkstra returns wrong results on negative costs
+    NegativeCost { edge: usize, cost: EdgeCost },
+    InvalidCoords { node: usize, lat: f64, lon: f64 },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::CountMismatch { what, declared, actual } =>
+                write!(f, "The file declares {} {} but contains {}", declared, what, actual),
+            Problem::OffsetOutOfRange { node, offset } =>
+                write!(f, "Node {} has the offset {}, which is outside of the edges", node, offset),
+            Problem::OffsetNotMonotone { node, offset, previous } =>
+                write!(f, "Node {} has the offset {}, which is smaller than the previous offset {}", node, offset, previous),
+            Problem::WrongSource { edge, src, node } =>
+                write!(f, "Edge {} has the source {} but is listed under node {}", edge, src, node),
+            Problem::TargetOutOfRange { edge, trg } =>
+                write!(f, "Edge {} has the target {}, which is not a node", edge, trg),
+            Problem::NegativeCost { edge, cost } =>
+                write!(f, "Edge {} has the negative cost {}", edge, cost),
+            Problem::InvalidCoords { node, lat, lon } =>
+                write!(f, "Node {} has the invalid coordinates {}, {}", node, lat, lon),
+        }
+    }
+}
+
+impl Problem {
+    /// Whether searches can't run on the graph at all, because they would look outside of the
+    /// nodes or edges or follow edges from the wrong nodes
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Problem::OffsetOutOfRange { .. } | Problem::OffsetNotMonotone { .. } | Problem::WrongSource { .. } | Problem::TargetOutOfRange { .. }
+        )
+    }
+}
+
+/// Checks the structure of a graph and returns every problem found. Edges are only checked against
+/// their source if the offsets are valid.
+pub fn validate(graph: &Graph) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let node_count = graph.node_count();
+    let edge_count = graph.edge_count();
+
+    if let Some((declared_nodes, declared_edges)) = graph.declared_counts() {
+        if declared_nodes != node_count {
+            problems.push(Problem::CountMismatch { what: "nodes", declared: declared_nodes, actual: node_count });
+        }
+        if declared_edges != edge_count {
+            problems.push(Problem::CountMismatch { what: "edges", declared: declared_edges, actual: edge_count });
+        }
+    }
+
+    let mut offsets_valid = true;
+    let mut previous = 0;
+    for (i, node) in graph.nodes().iter().enumerate() {
+        if node.offset < 0 || node.offset as usize > edge_count {
+            problems.push(Problem::OffsetOutOfRange { node: i, offset: node.offset });
+            offsets_valid = false;
+            continue;
+        }
+        if node.offset < previous {
+            problems.push(Problem::OffsetNotMonotone { node: i, offset: node.offset, previous });
+            offsets_valid = false;
+        }
+        previous = node.offset;
+
+        let (lat, lon) = (node.coords.lat, node.coords.lon);
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            problems.push(Problem::InvalidCoords { node: i, lat, lon });
+        }
+    }
+
+    if offsets_valid && node_count > 0 {
+        for node in 0..node_count {
+            for edge in graph.outgoing_edge_range(node) {
+                let src = graph.edge(edge).unwrap().src;
+                if src != node as NodeIndex {
+                    problems.push(Problem::WrongSource { edge, src, node });
+                }
+            }
+        }
+    }
+
+    for (i, edge) in graph.edges().iter().enumerate() {
+        if edge.trg < 0 || edge.trg as usize >= node_count {
+            problems.push(Problem::TargetOutOfRange { edge: i, trg: edge.trg });
+        }
+        if edge.cost < 0 {
+            problems.push(Problem::NegativeCost { edge: i, cost: edge.cost });
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Problem};
+    use crate::coords::Coords;
+    use crate::edge::Edge;
+    use crate::Graph;
+
+    #[test]
+    fn problems() {
+        let coords = vec![Coords::deg(48.0, 9.0), Coords::deg(95.0, 9.0), Coords::deg(48.0, 9.0)];
+        let edges = vec![Edge::new(0, 1, 3), Edge::new(1, 4, 1), Edge::new(2, 0, -2)];
+        let graph = Graph::from_edges(coords.clone(), edges);
+
+        assert_eq!(validate(&graph), vec![
+            Problem::InvalidCoords { node: 1, lat: 95.0, lon: 9.0 },
+            Problem::TargetOutOfRange { edge: 1, trg: 4 },
+            Problem::NegativeCost { edge: 2, cost: -2 },
+        ]);
+        assert_eq!(validate(&graph).iter().filter(|problem| problem.is_fatal()).count(), 1);
+        assert!(validate(&Graph::from_edges(coords[..1].to_vec(), vec![Edge::new(0, 0, 1)])).is_empty());
+    }
+}